@@ -0,0 +1,151 @@
+use crate::commands::gen_srf::{gen_srf_for_mod, open_cache_or_gen_srf};
+use crate::commands::sync;
+use crate::source::{self, Source};
+use crate::{mod_cache, repository};
+use snafu::{ensure, ResultExt, Snafu};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("Failed to fetch repository info: {}", source))]
+    RepositoryFetch { source: repository::Error },
+    #[snafu(display("Failed to serialize repository info: {}", source))]
+    RepositorySerialization { source: serde_json::Error },
+    #[snafu(display("Failed to open ModCache: {}", source))]
+    ModCacheOpen { source: mod_cache::Error },
+    #[snafu(display("{} is missing or outdated in {}, sync it first", name, path.display()))]
+    OutdatedMod { name: String, path: PathBuf },
+    #[snafu(display("Failed to open bundle: {}", source))]
+    BundleOpen { source: source::Error },
+    #[snafu(display("Failed to apply bundle: {}", source))]
+    Apply { source: sync::Error },
+}
+
+// packages repo.json and every required mod, as they are stored locally, into a tar archive
+pub fn export(source: &Source, base_path: &Path, out: &Path) -> Result<(), Error> {
+    let remote_repo = repository::get_repository_info(source).context(RepositoryFetchSnafu)?;
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+
+    // a bundle with outdated mods would be applied as if it was the real repository
+    for r#mod in &remote_repo.required_mods {
+        let up_to_date = mod_cache
+            .mods
+            .get(&r#mod.checksum)
+            .is_some_and(|cached| cached.name.eq_ignore_ascii_case(&r#mod.mod_name));
+
+        ensure!(
+            up_to_date,
+            OutdatedModSnafu {
+                name: &r#mod.mod_name,
+                path: base_path
+            }
+        );
+    }
+
+    let writer = BufWriter::new(File::create(out).context(IoSnafu)?);
+    let mut builder = tar::Builder::new(writer);
+
+    let repo_json = serde_json::to_vec(&remote_repo).context(RepositorySerializationSnafu)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(repo_json.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "repo.json", repo_json.as_slice())
+        .context(IoSnafu)?;
+
+    for r#mod in &remote_repo.required_mods {
+        let mod_path = base_path.join(Path::new(&r#mod.mod_name));
+
+        if !mod_path.join("mod.srf").exists() {
+            gen_srf_for_mod(&mod_path);
+        }
+
+        println!("adding {}", r#mod.mod_name);
+
+        builder
+            .append_dir_all(&r#mod.mod_name, &mod_path)
+            .context(IoSnafu)?;
+    }
+
+    builder.into_inner().context(IoSnafu)?;
+
+    Ok(())
+}
+
+// bundles are applied exactly like a repository, so only changed files are copied
+pub fn import(bundle: &Path, base_path: &Path, dry_run: bool) -> Result<(), Error> {
+    let source = Source::from_bundle(bundle).context(BundleOpenSnafu)?;
+
+    sync::sync(&source, base_path, dry_run, false).context(ApplySnafu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::md5_digest::Md5Digest;
+    use crate::mod_cache::ModCache;
+
+    fn write_repo(repo_path: &Path, checksum: &str) {
+        let repo = format!(
+            r#"{{"repoName":"bundled","checksum":"","requiredMods":[{{"modName":"@ace","checkSum":"{checksum}","enabled":true}}],"optionalMods":[],"clientParameters":"","repoBasicAuthentication":null,"version":"1","servers":[]}}"#
+        );
+
+        std::fs::write(repo_path.join("repo.json"), repo).unwrap();
+    }
+
+    #[test]
+    fn export_then_import_roundtrip() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let ace_path: PathBuf = [project_root, "test_files", "@ace"].iter().collect();
+
+        let exporter = tempfile::tempdir().unwrap();
+        let mod_path = exporter.path().join("@ace");
+        std::fs::create_dir_all(mod_path.join("addons")).unwrap();
+        for entry in std::fs::read_dir(ace_path.join("addons")).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(
+                entry.path(),
+                mod_path.join("addons").join(entry.file_name()),
+            )
+            .unwrap();
+        }
+        write_repo(exporter.path(), "787662722D70C36DF28CD1D5EE8D8E86");
+
+        let agent = ureq::AgentBuilder::new().build();
+        let source = Source::new(agent, &exporter.path().to_string_lossy()).unwrap();
+        let bundle = exporter.path().join("bundle.tar");
+        export(&source, exporter.path(), &bundle).unwrap();
+
+        let importer = tempfile::tempdir().unwrap();
+        import(&bundle, importer.path(), false).unwrap();
+
+        let cache = ModCache::from_disk(importer.path()).unwrap();
+        let r#mod = &cache.mods[&Md5Digest::new("787662722D70C36DF28CD1D5EE8D8E86").unwrap()];
+        assert!(r#mod.is_referenced_by("bundled"));
+        assert!(importer
+            .path()
+            .join("@ace/addons/ace_advanced_ballistics.pbo")
+            .exists());
+    }
+
+    #[test]
+    fn export_refuses_outdated_mods() {
+        let exporter = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(exporter.path().join("@ace")).unwrap();
+        write_repo(exporter.path(), "00000000000000000000000000000000");
+
+        let agent = ureq::AgentBuilder::new().build();
+        let source = Source::new(agent, &exporter.path().to_string_lossy()).unwrap();
+        let result = export(
+            &source,
+            exporter.path(),
+            &exporter.path().join("bundle.tar"),
+        );
+
+        assert!(matches!(result, Err(Error::OutdatedMod { .. })));
+    }
+}
@@ -0,0 +1,662 @@
+use crate::commands::gen_srf::open_cache_or_gen_srf;
+use crate::commands::launch::selected_mods;
+use crate::mod_cache;
+use crate::pbo;
+use crate::rap;
+use crate::signature::{self, BiKey, BiSign};
+use rayon::prelude::*;
+use snafu::{ResultExt, Snafu};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to open ModCache: {}", source))]
+    ModCacheOpen { source: mod_cache::Error },
+}
+
+struct ModPbo {
+    mod_name: String,
+    mod_path: PathBuf,
+    path: PathBuf,
+}
+
+impl ModPbo {
+    fn relative_path(&self) -> String {
+        self.path
+            .strip_prefix(&self.mod_path)
+            .unwrap_or(&self.path)
+            .display()
+            .to_string()
+    }
+}
+
+pub fn is_pbo(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pbo"))
+}
+
+// mods in storage, as (name, directory) pairs
+pub fn mod_dirs(base_path: &Path, mods: &[&mod_cache::Mod]) -> Vec<(String, PathBuf)> {
+    let mut mods: Vec<_> = mods
+        .iter()
+        .map(|r#mod| (r#mod.name.clone(), base_path.join(&r#mod.name)))
+        .collect();
+    mods.sort();
+    mods
+}
+
+// every mod directory in storage. the ModCache is keyed by checksum, so it only knows one of
+// several identical copies of a mod, which are exactly the ones that conflict
+fn disk_mod_dirs(base_path: &Path) -> Vec<(String, PathBuf)> {
+    let mut mods: Vec<_> = WalkDir::new(base_path)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_dir() && e.file_name().to_string_lossy().starts_with('@'))
+        .map(|e| (e.file_name().to_string_lossy().to_string(), e.into_path()))
+        .collect();
+    mods.sort();
+    mods
+}
+
+// the mod directories launch would load, going by their names
+fn selected_mod_dirs(
+    base_path: &Path,
+    mod_cache: &mod_cache::ModCache,
+    repo_name: Option<&str>,
+) -> Vec<(String, PathBuf)> {
+    let selected = selected_mods(mod_cache, repo_name);
+    let declared = mod_cache.declared_order(repo_name);
+    let disabled: Vec<_> = mod_cache
+        .mods
+        .values()
+        .filter(|r#mod| !r#mod.enabled)
+        .map(|r#mod| r#mod.name.as_str())
+        .collect();
+
+    disk_mod_dirs(base_path)
+        .into_iter()
+        .filter(|(name, _)| {
+            let is_named = |other: &str| other.eq_ignore_ascii_case(name);
+            let wanted = repo_name.is_none()
+                || selected.iter().any(|r#mod| is_named(&r#mod.name))
+                || declared.iter().copied().any(is_named);
+
+            wanted && !disabled.iter().copied().any(is_named)
+        })
+        .collect()
+}
+
+fn mod_pbos(mod_dirs: &[(String, PathBuf)]) -> Vec<ModPbo> {
+    mod_dirs
+        .iter()
+        .flat_map(|(mod_name, mod_path)| {
+            WalkDir::new(mod_path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file() && is_pbo(e.path()))
+                .map(|e| ModPbo {
+                    mod_name: mod_name.clone(),
+                    mod_path: mod_path.clone(),
+                    path: e.into_path(),
+                })
+        })
+        .collect()
+}
+
+fn verify_pbo(path: &Path) -> Result<(), pbo::Error> {
+    let file = File::open(path).map_err(|source| pbo::Error::Io { source })?;
+
+    pbo::verify_file(BufReader::new(file))
+}
+
+// verifies the trailing checksum of every pbo. returns how many are corrupted
+pub fn integrity(base_path: &Path) -> Result<usize, Error> {
+    let pbos = mod_pbos(&disk_mod_dirs(base_path));
+
+    let results: Vec<_> = pbos
+        .par_iter()
+        .map(|pbo| (pbo, verify_pbo(&pbo.path)))
+        .collect();
+
+    let mut corrupted = 0;
+
+    for (pbo, result) in results {
+        match result {
+            Ok(()) => {}
+            Err(pbo::Error::MissingChecksum) => {
+                println!("{}: {}: no checksum", pbo.mod_name, pbo.relative_path());
+            }
+            Err(e) => {
+                corrupted += 1;
+                println!("{}: {}: {e}", pbo.mod_name, pbo.relative_path());
+            }
+        }
+    }
+
+    println!("checked {} pbos, {corrupted} corrupted", pbos.len());
+
+    Ok(corrupted)
+}
+
+// pbos are mounted at their prefix, falling back to their file name. the game compares them case
+// insensitively and doesn't care about leading or trailing slashes
+fn mount_point(pbo: &ModPbo) -> Result<String, pbo::Error> {
+    let file = File::open(&pbo.path).map_err(|source| pbo::Error::Io { source })?;
+    let header = pbo::Pbo::read(BufReader::new(file))?;
+
+    let prefix = match header.prefix() {
+        Some(prefix) if !prefix.is_empty() => prefix.to_string(),
+        _ => pbo
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+
+    Ok(prefix.replace('/', "\\").trim_matches('\\').to_lowercase())
+}
+
+fn report_duplicates(kind: &str, groups: BTreeMap<String, Vec<&ModPbo>>) -> usize {
+    let mut conflicts = 0;
+
+    for (key, pbos) in groups.into_iter().filter(|(_, pbos)| pbos.len() > 1) {
+        conflicts += 1;
+        println!("duplicate {kind} {key}:");
+
+        for pbo in pbos {
+            println!("    {}: {}", pbo.mod_name, pbo.relative_path());
+        }
+    }
+
+    conflicts
+}
+
+// looks for pbos that would override each other in game. returns how many conflicts were found
+pub fn conflicts(base_path: &Path, repo_name: Option<&str>) -> Result<usize, Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let pbos = mod_pbos(&selected_mod_dirs(base_path, &mod_cache, repo_name));
+
+    let mount_points: Vec<_> = pbos.par_iter().map(|pbo| (pbo, mount_point(pbo))).collect();
+
+    let mut prefixes: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    let mut file_names: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+    for (pbo, mount_point) in mount_points {
+        match mount_point {
+            Ok(mount_point) => prefixes.entry(mount_point).or_default().push(pbo),
+            Err(e) => println!(
+                "{}: {}: failed to read header: {e}",
+                pbo.mod_name,
+                pbo.relative_path()
+            ),
+        }
+
+        let file_name = pbo
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        file_names.entry(file_name).or_default().push(pbo);
+    }
+
+    let conflicts = report_duplicates("prefix", prefixes) + report_duplicates("pbo", file_names);
+
+    println!("checked {} pbos, {conflicts} conflicts", pbos.len());
+
+    Ok(conflicts)
+}
+
+// vanilla addons are always loaded, so nothing has to provide them
+const BASE_GAME_ADDONS: &[&str] = &["a3_*"];
+
+// entries ending in * match every addon starting with the rest
+fn is_ignored(addon: &str, ignore: &[String]) -> bool {
+    let addon = addon.to_lowercase();
+
+    BASE_GAME_ADDONS
+        .iter()
+        .copied()
+        .chain(ignore.iter().map(String::as_str))
+        .map(str::to_lowercase)
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => addon.starts_with(prefix),
+            None => addon == pattern,
+        })
+}
+
+fn read_patches(pbo: &ModPbo) -> Result<Option<Vec<rap::Patch>>, pbo::Error> {
+    let file = File::open(&pbo.path).map_err(|source| pbo::Error::Io { source })?;
+    let config = pbo::Pbo::read(BufReader::new(file))?.read_config()?;
+
+    Ok(config.as_ref().map(rap::cfg_patches))
+}
+
+// looks for requiredAddons that none of the mods launch would load provide. returns how many are
+// missing
+pub fn deps(base_path: &Path, repo_name: Option<&str>, ignore: &[String]) -> Result<usize, Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let mods = selected_mods(&mod_cache, repo_name);
+
+    Ok(deps_for_mods(&mod_dirs(base_path, &mods), ignore))
+}
+
+// the dependency check for mods that were already chosen, as (name, directory) pairs. they don't
+// have to be in storage, e.g. workshop items
+pub fn deps_for_mods(mod_dirs: &[(String, PathBuf)], ignore: &[String]) -> usize {
+    let pbos = mod_pbos(mod_dirs);
+
+    let results: Vec<_> = pbos
+        .par_iter()
+        .map(|pbo| (pbo, read_patches(pbo)))
+        .collect();
+
+    let mut provided = HashSet::new();
+    let mut patches = Vec::new();
+
+    for (pbo, result) in results {
+        match result {
+            Ok(Some(pbo_patches)) => {
+                for patch in pbo_patches {
+                    provided.insert(patch.name.to_lowercase());
+                    patches.push((pbo, patch));
+                }
+            }
+            // text configs aren't parsed, so anything they provide shows up as missing
+            Ok(None) => {}
+            Err(e) => println!(
+                "{}: {}: failed to read config: {e}",
+                pbo.mod_name,
+                pbo.relative_path()
+            ),
+        }
+    }
+
+    let mut missing = 0;
+
+    for (pbo, patch) in patches {
+        for addon in &patch.required_addons {
+            if provided.contains(&addon.to_lowercase()) || is_ignored(addon, ignore) {
+                continue;
+            }
+
+            missing += 1;
+            println!(
+                "{}: {}: {} requires {addon}, which no loaded mod provides",
+                pbo.mod_name,
+                pbo.relative_path(),
+                patch.name
+            );
+        }
+    }
+
+    println!(
+        "checked {} pbos, {missing} missing dependencies",
+        pbos.len()
+    );
+
+    missing
+}
+
+fn is_dir_named(path: &Path, name: &str) -> bool {
+    path.is_dir()
+        && path
+            .file_name()
+            .is_some_and(|file_name| file_name.eq_ignore_ascii_case(name))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+// bikeys in the keys folder of a mod, whatever its case
+pub fn mod_key_files(mod_path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(mod_path) else {
+        return Vec::new();
+    };
+
+    let mut key_paths: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_dir_named(path, "keys"))
+        .flat_map(|keys_path| WalkDir::new(keys_path).into_iter().filter_map(Result::ok))
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file() && has_extension(path, "bikey"))
+        .collect();
+    key_paths.sort();
+
+    key_paths
+}
+
+// every key shipped in the keys folder of a mod, by authority
+fn mod_keys(base_path: &Path, mods: &[&mod_cache::Mod]) -> HashMap<String, BiKey> {
+    let mut keys = HashMap::new();
+
+    for r#mod in mods {
+        for path in mod_key_files(&base_path.join(&r#mod.name)) {
+            let key = File::open(&path)
+                .map_err(|source| signature::Error::Io { source })
+                .and_then(|file| BiKey::read(BufReader::new(file)));
+
+            match key {
+                Ok(key) => {
+                    keys.insert(key.authority.to_lowercase(), key);
+                }
+                Err(e) => println!("{}: {}: {e}", r#mod.name, path.display()),
+            }
+        }
+    }
+
+    keys
+}
+
+// bisigns are stored next to the pbo, as <pbo name>.<authority>.bisign
+fn pbo_signatures(pbo: &ModPbo) -> Vec<PathBuf> {
+    let Some(pbo_name) = pbo.path.file_name() else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", pbo_name.to_string_lossy().to_lowercase());
+
+    let Some(Ok(entries)) = pbo.path.parent().map(std::fs::read_dir) else {
+        return Vec::new();
+    };
+
+    let mut signatures: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            name.starts_with(&prefix) && has_extension(path, "bisign")
+        })
+        .collect();
+    signatures.sort();
+
+    signatures
+}
+
+fn verify_signature(
+    pbo: &ModPbo,
+    signature_path: &Path,
+    keys: &HashMap<String, BiKey>,
+) -> Result<(), String> {
+    let sign = File::open(signature_path)
+        .map_err(|source| signature::Error::Io { source })
+        .and_then(|file| BiSign::read(BufReader::new(file)))
+        .map_err(|e| e.to_string())?;
+
+    let key = keys
+        .get(&sign.authority.to_lowercase())
+        .ok_or_else(|| format!("no key for {}", sign.authority))?;
+
+    let file = File::open(&pbo.path).map_err(|e| e.to_string())?;
+    let mut header = pbo::Pbo::read(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    sign.verify(key, &mut header).map_err(|e| e.to_string())
+}
+
+// verifies every bisign against the keys the mods ship. returns how many pbos are unsigned, signed
+// with an unknown key or have an invalid signature
+pub fn signatures(base_path: &Path, repo_name: Option<&str>) -> Result<usize, Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let mods = selected_mods(&mod_cache, repo_name);
+    let pbos = mod_pbos(&selected_mod_dirs(base_path, &mod_cache, repo_name));
+    let keys = mod_keys(base_path, &mods);
+
+    let results: Vec<_> = pbos
+        .par_iter()
+        .map(|pbo| {
+            let results: Vec<_> = pbo_signatures(pbo)
+                .into_iter()
+                .map(|path| {
+                    let result = verify_signature(pbo, &path, &keys);
+                    (path, result)
+                })
+                .collect();
+
+            (pbo, results)
+        })
+        .collect();
+
+    let mut problems = 0;
+
+    for (pbo, results) in results {
+        if results.is_empty() {
+            problems += 1;
+            println!("{}: {}: not signed", pbo.mod_name, pbo.relative_path());
+        }
+
+        for (path, result) in results {
+            if let Err(e) = result {
+                problems += 1;
+                println!(
+                    "{}: {}: {e}",
+                    pbo.mod_name,
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                );
+            }
+        }
+    }
+
+    println!(
+        "checked {} pbos against {} keys, {problems} problems",
+        pbos.len(),
+        keys.len()
+    );
+
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gen_srf::gen_srf;
+
+    #[test]
+    fn integrity_test() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let pbo_path: PathBuf = [
+            project_root,
+            "test_files",
+            "@ace",
+            "addons",
+            "ace_advanced_ballistics.pbo",
+        ]
+        .iter()
+        .collect();
+
+        let base = tempfile::tempdir().unwrap();
+        let addons = base.path().join("@ace").join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        std::fs::copy(&pbo_path, addons.join("intact.pbo")).unwrap();
+
+        let mut tampered = std::fs::read(&pbo_path).unwrap();
+        tampered[5000] ^= 0xFF;
+        std::fs::write(addons.join("tampered.pbo"), tampered).unwrap();
+
+        gen_srf(base.path());
+
+        assert_eq!(integrity(base.path()).unwrap(), 1);
+    }
+
+    #[test]
+    fn conflicts_test() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let pbo_path: PathBuf = [
+            project_root,
+            "test_files",
+            "@ace",
+            "addons",
+            "ace_advanced_ballistics.pbo",
+        ]
+        .iter()
+        .collect();
+
+        let base = tempfile::tempdir().unwrap();
+        for mod_name in ["@ace", "@ace_fork"] {
+            let addons = base.path().join(mod_name).join("addons");
+            std::fs::create_dir_all(&addons).unwrap();
+            std::fs::copy(&pbo_path, addons.join("ace_advanced_ballistics.pbo")).unwrap();
+        }
+
+        // same prefix as ace, under another name
+        let addons = base.path().join("@ace_compat").join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        let mut writer = pbo::PboWriter::new();
+        writer.extension("prefix", "z\\ace\\addons\\advanced_ballistics\\");
+        writer
+            .write(File::create(addons.join("compat.pbo")).unwrap())
+            .unwrap();
+
+        // no prefix, so it is mounted as its file name
+        let addons = base.path().join("@unrelated").join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        pbo::PboWriter::new()
+            .write(File::create(addons.join("unrelated.pbo")).unwrap())
+            .unwrap();
+
+        gen_srf(base.path());
+
+        // one duplicate prefix, one duplicate file name
+        assert_eq!(conflicts(base.path(), None).unwrap(), 2);
+    }
+
+    // a config.bin with nothing but empty CfgPatches classes
+    fn patches_config(names: &[&str]) -> Vec<u8> {
+        let mut config = b"\0raP".to_vec();
+        config.extend([0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0]);
+
+        // root class, with CfgPatches as its only entry
+        config.extend(b"\0\x01\0CfgPatches\0");
+        let cfg_patches_body = config.len() + 4;
+        config.extend((cfg_patches_body as u32).to_le_bytes());
+
+        config.extend([0, names.len() as u8]);
+        let bodies_start = config.len() + names.iter().map(|name| name.len() + 6).sum::<usize>();
+
+        for (index, name) in names.iter().enumerate() {
+            config.push(0);
+            config.extend(name.as_bytes());
+            config.push(0);
+            config.extend(((bodies_start + index * 2) as u32).to_le_bytes());
+        }
+
+        for _ in names {
+            config.extend([0, 0]);
+        }
+
+        config
+    }
+
+    #[test]
+    fn deps_test() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let pbo_path: PathBuf = [
+            project_root,
+            "test_files",
+            "@ace",
+            "addons",
+            "ace_advanced_ballistics.pbo",
+        ]
+        .iter()
+        .collect();
+
+        // ace_advanced_ballistics requires ace_ballistics and ace_weather
+        let base = tempfile::tempdir().unwrap();
+        let addons = base.path().join("@ace").join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        std::fs::copy(&pbo_path, addons.join("ace_advanced_ballistics.pbo")).unwrap();
+
+        let addons = base.path().join("@ace_ballistics").join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        let mut writer = pbo::PboWriter::new();
+        writer
+            .add_data("config.bin", patches_config(&["ace_ballistics"]), 0)
+            .unwrap();
+        writer
+            .write(File::create(addons.join("ace_ballistics.pbo")).unwrap())
+            .unwrap();
+
+        gen_srf(base.path());
+
+        assert_eq!(deps(base.path(), None, &[]).unwrap(), 1);
+        assert_eq!(
+            deps(base.path(), None, &["ACE_WEATHER".to_string()]).unwrap(),
+            0
+        );
+        assert_eq!(deps(base.path(), None, &["ace_*".to_string()]).unwrap(), 0);
+
+        // launch adds workshop items, which live outside of storage
+        let workshop = tempfile::tempdir().unwrap();
+        let addons = workshop.path().join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        let mut writer = pbo::PboWriter::new();
+        writer
+            .add_data("config.bin", patches_config(&["ace_weather"]), 0)
+            .unwrap();
+        writer
+            .write(File::create(addons.join("ace_weather.pbo")).unwrap())
+            .unwrap();
+
+        let mod_cache = open_cache_or_gen_srf(base.path()).unwrap();
+        let mut mod_dirs = mod_dirs(base.path(), &selected_mods(&mod_cache, None));
+        assert_eq!(deps_for_mods(&mod_dirs, &[]), 1);
+
+        mod_dirs.push(("463939057".to_string(), workshop.path().to_owned()));
+        assert_eq!(deps_for_mods(&mod_dirs, &[]), 0);
+    }
+
+    #[test]
+    fn signatures_test() {
+        use crate::signature::test_support::{bikey, bisign, signed_pbo};
+
+        let pbo = signed_pbo();
+
+        let base = tempfile::tempdir().unwrap();
+        let mod_path = base.path().join("@signed");
+        let addons = mod_path.join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        std::fs::create_dir_all(mod_path.join("Keys")).unwrap();
+        std::fs::write(mod_path.join("Keys").join("signed.bikey"), bikey("signed")).unwrap();
+
+        std::fs::write(addons.join("valid.pbo"), &pbo).unwrap();
+        std::fs::write(
+            addons.join("valid.pbo.signed.bisign"),
+            bisign("signed", &pbo),
+        )
+        .unwrap();
+
+        std::fs::write(addons.join("unsigned.pbo"), &pbo).unwrap();
+
+        std::fs::write(addons.join("unknown.pbo"), &pbo).unwrap();
+        std::fs::write(
+            addons.join("unknown.pbo.someone_else.bisign"),
+            bisign("someone_else", &pbo),
+        )
+        .unwrap();
+
+        let mut tampered = pbo.clone();
+        let len = tampered.len();
+        tampered[len - 30] ^= 0xFF;
+        std::fs::write(addons.join("tampered.pbo"), tampered).unwrap();
+        std::fs::write(
+            addons.join("tampered.pbo.signed.bisign"),
+            bisign("signed", &pbo),
+        )
+        .unwrap();
+
+        gen_srf(base.path());
+
+        assert_eq!(signatures(base.path(), None).unwrap(), 3);
+    }
+}
@@ -49,7 +49,34 @@ pub fn gen_srf(base_path: &Path) {
         })
         .collect();
 
-    let cache = ModCache::new(mods);
+    let mut cache = ModCache::new(mods);
+
+    // keep track of which repositories referenced the mods we just rescanned
+    if let Ok(mut previous) = ModCache::from_disk(base_path) {
+        for r#mod in cache.mods.values_mut() {
+            for old in previous.remove_by_name(&r#mod.name) {
+                r#mod.repos.extend(old.repos);
+                r#mod.enabled = old.enabled;
+            }
+        }
+
+        // and the order of the repositories that still reference any of them
+        cache.load_orders = previous
+            .load_orders
+            .into_iter()
+            .filter(|(repo_name, _)| {
+                cache
+                    .mods
+                    .values()
+                    .any(|r#mod| r#mod.repos.contains(repo_name))
+            })
+            .collect();
+        cache.optional_mods = previous
+            .optional_mods
+            .into_iter()
+            .filter(|(repo_name, _)| cache.load_orders.contains_key(repo_name))
+            .collect();
+    }
 
     cache.to_disk(base_path).unwrap();
 }
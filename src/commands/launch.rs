@@ -1,96 +1,979 @@
+use crate::commands::check;
 use crate::commands::gen_srf::open_cache_or_gen_srf;
+use crate::config::{Config, Profile};
 use crate::mod_cache;
 use crate::mod_cache::ModCache;
-use snafu::{ResultExt, Snafu};
-use std::cfg;
+use crate::mod_info::ModInfo;
+use crate::{preset, steam};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
 use std::path::{Path, PathBuf};
-
-#[cfg(not(windows))]
-use snafu::OptionExt;
+use std::process::Command;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("failed to open ModCache: {}", source))]
     ModCacheOpen { source: mod_cache::Error },
-    #[snafu(display("failed to find drive_c"))]
-    #[cfg(not(windows))]
-    FailedToFindDriveC,
+    #[snafu(display("no mods are referenced by repository {}", repo_name))]
+    NoModsForRepo { repo_name: String },
+    #[snafu(display("failed to read config: {}", source))]
+    Config { source: crate::config::Error },
+    #[snafu(display("there is no profile named {}", profile))]
+    UnknownProfile { profile: String },
+    #[snafu(display("{} required addons are missing", count))]
+    MissingDependencies { count: usize },
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("failed to find Arma 3: {}", source))]
+    ArmaNotFound { source: steam::Error },
+    #[snafu(display("Arma 3 has no proton prefix, run it through Steam once"))]
+    NoPrefix,
+    #[snafu(display("pass --proton or --wrapper to launch directly"))]
+    NoRunner,
+    #[snafu(display("failed to start {}: {}", program, source))]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("failed to open {}: {}", url, source))]
+    OpenUrl { url: String, source: std::io::Error },
+    #[snafu(display("failed to read {}: {}", path.display(), source))]
+    ReadPreset {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("failed to parse {}: {}", path.display(), source))]
+    Preset {
+        path: PathBuf,
+        source: preset::Error,
+    },
+    #[snafu(display(
+        "workshop items are loaded from Arma 3's Steam library, but {}",
+        reason
+    ))]
+    NoSteamLibrary { reason: String },
+    #[snafu(display("workshop item {} is not downloaded to {}, subscribe to it in Steam", published_id, path.display()))]
+    WorkshopItemMissing { published_id: u64, path: PathBuf },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Backend {
+    // asks steam to start the game through a steam:// url
+    #[default]
+    Steam,
+    // starts the game executable ourselves and waits for it to exit
+    Direct,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Platform {
+    // the windows build on windows, which takes host paths
+    Windows,
+    // the windows build through proton, which takes paths inside its wine prefix
+    Proton,
+    // the linux port, which takes host paths
+    Native,
+}
+
+impl Platform {
+    // the linux port is used whenever steam installed it, there's no other way to tell
+    pub fn detect(arma: Option<&steam::Arma>) -> Self {
+        if cfg!(windows) {
+            Platform::Windows
+        } else if arma.is_some_and(|arma| arma.native_executable().is_file()) {
+            Platform::Native
+        } else {
+            Platform::Proton
+        }
+    }
+
+    fn separator(self) -> char {
+        match self {
+            Platform::Windows | Platform::Proton => '\\',
+            Platform::Native => '/',
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Options<'a> {
+    // only load the mods this repository references
+    pub repo_name: Option<&'a str>,
+    // load exactly the mods of this profile from the config, instead of a repository's
+    pub profile: Option<&'a str>,
+    // enables the dependency check, with the addons it should assume are present
+    pub deps_ignore: Option<&'a [String]>,
+    pub steam_root: Option<&'a Path>,
+    pub backend: Backend,
+    // the proton script to run the game with, for the direct backend
+    pub proton: Option<&'a Path>,
+    // a command the game executable and its arguments are appended to, instead of proton
+    pub wrapper: Option<&'a [String]>,
+    // pass the arguments through a -par= file instead of the command line
+    pub par_file: bool,
+    // print what would be run instead of running it
+    pub dry_run: bool,
+    // detected from the steam install if not given
+    pub platform: Option<Platform>,
+    // workshop items to load on top of the mods in storage
+    pub workshop: &'a [u64],
+    // a launcher preset whose workshop items are loaded like the ones above
+    pub workshop_preset: Option<&'a Path>,
+}
+
+// written to the mod storage path, which the game can already reach
+const PAR_FILE_NAME: &str = "nimble-launch.par";
+
+// mods in storage. if a repository was chosen, only the mods it references. they are in the order
+// the repositories declare them, mods no repository declares follow by name
+pub fn repo_mods<'a>(
+    mod_cache: &'a ModCache,
+    repo_name: Option<&'a str>,
+) -> Vec<&'a mod_cache::Mod> {
+    let mut mods: Vec<_> = match repo_name {
+        Some(repo_name) => mod_cache.mods_for_repo(repo_name).collect(),
+        None => mod_cache.mods.values().collect(),
+    };
+
+    let declared = mod_cache.declared_order(repo_name);
+    mods.sort_by_cached_key(|r#mod| {
+        let position = declared
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(&r#mod.name));
+
+        (position.unwrap_or(usize::MAX), r#mod.name.to_lowercase())
+    });
+
+    mods
+}
+
+// mods to load, which leaves out the ones that are disabled
+pub fn selected_mods<'a>(
+    mod_cache: &'a ModCache,
+    repo_name: Option<&'a str>,
+) -> Vec<&'a mod_cache::Mod> {
+    repo_mods(mod_cache, repo_name)
+        .into_iter()
+        .filter(|r#mod| r#mod.enabled)
+        .collect()
 }
 
-fn generate_mod_args(base_path: &Path, mod_cache: &ModCache) -> String {
-    mod_cache
+// the mods of a profile that are in storage, in the profile's order. enabled or not
+fn profile_mods<'a>(mod_cache: &'a ModCache, profile: &Profile) -> Vec<&'a mod_cache::Mod> {
+    profile
         .mods
-        .values()
-        .fold(String::from("-noLauncher -mod="), |acc, r#mod| {
-            let mod_name = &r#mod.name;
-            let full_path = base_path
-                .join(Path::new(mod_name))
-                .to_string_lossy()
-                .to_string();
-            format!("{acc}{full_path};")
+        .iter()
+        .filter_map(|name| {
+            let r#mod = mod_cache
+                .mods
+                .values()
+                .find(|r#mod| r#mod.name.eq_ignore_ascii_case(name));
+
+            if r#mod.is_none() {
+                println!("{name} is in the profile but not in storage, skipping it");
+            }
+
+            r#mod
         })
+        .collect()
+}
+
+// mods to load in the order they are loaded in, after the rules in the config. a profile replaces
+// the repository's mods
+pub fn load_order<'a>(
+    base_path: &Path,
+    mod_cache: &'a ModCache,
+    repo_name: Option<&'a str>,
+    profile: Option<&str>,
+) -> Result<Vec<&'a mod_cache::Mod>, Error> {
+    let config = Config::from_disk_or_default(base_path).context(ConfigSnafu)?;
+
+    let mods = match profile {
+        Some(profile) => profile_mods(
+            mod_cache,
+            config
+                .profiles
+                .get(profile)
+                .context(UnknownProfileSnafu { profile })?,
+        ),
+        None => selected_mods(mod_cache, repo_name),
+    };
+
+    Ok(config.load_order.apply(mods, |r#mod| &r#mod.name))
+}
+
+// base_path is the storage path as seen by the game, other_paths are more mods as seen by the game
+fn generate_mod_args(
+    platform: Platform,
+    base_path: &str,
+    mods: &[&mod_cache::Mod],
+    other_paths: &[String],
+) -> Vec<String> {
+    let separator = platform.separator();
+    let base_path = base_path.trim_end_matches(separator);
+
+    let mod_arg = mods
+        .iter()
+        .map(|r#mod| format!("{base_path}{separator}{}", r#mod.name))
+        .chain(other_paths.iter().cloned())
+        .fold(String::from("-mod="), |acc, path| format!("{acc}{path};"));
+
+    vec!["-noLauncher".to_string(), mod_arg]
+}
+
+// one parameter per line, as is. a line is a whole argument, so spaces need no quoting
+fn par_file_contents(args: &[String]) -> String {
+    args.iter().map(|arg| format!("{arg}\r\n")).collect()
+}
+
+// the arguments that point the game to the parameter file
+fn par_file_args(platform: Platform, game_base_path: &str) -> Vec<String> {
+    let separator = platform.separator();
+    let par_path = format!(
+        "{}{separator}{PAR_FILE_NAME}",
+        game_base_path.trim_end_matches(separator)
+    );
+
+    vec![format!("-par={par_path}")]
+}
+
+// moves the mod list into the parameter file. the rest stays on the command line, as arguments
+// like -noLauncher are read before the parameter file is. returns the command line and the file
+fn split_par_file(
+    args: Vec<String>,
+    platform: Platform,
+    game_base_path: &str,
+) -> (Vec<String>, String) {
+    let (mod_args, mut command_line): (Vec<_>, Vec<_>) =
+        args.into_iter().partition(|arg| arg.starts_with("-mod="));
+
+    command_line.extend(par_file_args(platform, game_base_path));
+
+    (command_line, par_file_contents(&mod_args))
+}
+
+// steam hands the command line to the game as it is, which splits it the way windows does. paths
+// with spaces have to be quoted, and backslashes before a quote doubled so they stay backslashes
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(char::is_whitespace) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                backslashes = 0;
+            }
+        }
+
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+fn steam_url(args: &[String]) -> String {
+    let cmdline = args
+        .iter()
+        .map(|arg| quote_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cmdline =
+        percent_encoding::utf8_percent_encode(&cmdline, percent_encoding::NON_ALPHANUMERIC);
+
+    format!("steam://run/{}//{cmdline}/", steam::ARMA_APP_ID)
+}
+
+// the command that starts the game without steam's help
+fn direct_command(
+    platform: Platform,
+    arma: &steam::Arma,
+    proton: Option<&Path>,
+    wrapper: Option<&[String]>,
+    args: &[String],
+) -> Result<Command, Error> {
+    let executable = match platform {
+        Platform::Windows | Platform::Proton => arma.executable(),
+        Platform::Native => arma.native_executable(),
+    };
+    let wrapper = wrapper.and_then(|wrapper| wrapper.split_first());
+
+    let mut command = if platform == Platform::Windows {
+        Command::new(executable)
+    } else if let Some((program, wrapper_args)) = wrapper {
+        let mut command = Command::new(program);
+        command.args(wrapper_args).arg(executable);
+        command
+    } else if platform == Platform::Native {
+        Command::new(executable)
+    } else {
+        let proton = proton.context(NoRunnerSnafu)?;
+        let compat_data = arma.compat_data().context(NoPrefixSnafu)?;
+
+        let mut command = Command::new(proton);
+        command
+            .arg("run")
+            .arg(executable)
+            .env("STEAM_COMPAT_DATA_PATH", compat_data)
+            .env("STEAM_COMPAT_CLIENT_INSTALL_PATH", &arma.steam_root)
+            .env("SteamAppId", steam::ARMA_APP_ID)
+            .env("SteamGameId", steam::ARMA_APP_ID);
+        command
+    };
+
+    command.args(args).current_dir(&arma.install_dir);
+
+    Ok(command)
+}
+
+// the command as it would be typed into a shell, environment included
+fn describe_command(command: &Command) -> String {
+    let quote = |word: &std::ffi::OsStr| {
+        let word = word.to_string_lossy();
+        let safe = |c: char| c.is_ascii_alphanumeric() || "-_=/.,:@+%".contains(c);
+
+        if !word.is_empty() && word.chars().all(safe) {
+            word.to_string()
+        } else {
+            format!("'{}'", word.replace('\'', "'\\''"))
+        }
+    };
+
+    let envs = command.get_envs().filter_map(|(key, value)| {
+        value.map(|value| format!("{}={}", key.to_string_lossy(), quote(value)))
+    });
+    let words = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(quote);
+
+    envs.chain(words).collect::<Vec<_>>().join(" ")
 }
 
 // if we're on windows we don't have to do anything
 #[cfg(windows)]
-fn convert_host_base_path_to_proton_base_path(host_base_path: &Path) -> Result<PathBuf, Error> {
-    Ok(host_base_path.to_owned())
+fn convert_host_base_path_to_proton_base_path(
+    host_base_path: &Path,
+    _drive_c: Option<&Path>,
+) -> String {
+    host_base_path.to_string_lossy().to_string()
 }
 
-// if we're not on windows, try to find a "drive_c" dir in the ancestors of base_path
+// if we're not on windows, paths inside the prefix's "drive_c" are on C:. anything else is reached
+// through Z:, which wine maps to the host's root. without a known prefix, any "drive_c" will do
 #[cfg(not(windows))]
-fn convert_host_base_path_to_proton_base_path(host_base_path: &Path) -> Result<PathBuf, Error> {
-    let drive_c_path = host_base_path
-        .ancestors()
-        .find(|&x| x.ends_with("drive_c"))
-        .context(FailedToFindDriveCSnafu)?;
+fn convert_host_base_path_to_proton_base_path(
+    host_base_path: &Path,
+    drive_c: Option<&Path>,
+) -> String {
+    let drive_c_path = match drive_c {
+        Some(drive_c) => host_base_path.starts_with(drive_c).then_some(drive_c),
+        None => host_base_path.ancestors().find(|&x| x.ends_with("drive_c")),
+    };
 
-    let relative = host_base_path
-        .strip_prefix(drive_c_path)
-        .expect("drive_c_path was not a prefix of host_base_path, this should never happen");
+    let (drive, relative) = match drive_c_path {
+        Some(drive_c_path) => (
+            "C:",
+            host_base_path.strip_prefix(drive_c_path).expect(
+                "drive_c_path was not a prefix of host_base_path, this should never happen",
+            ),
+        ),
+        None => (
+            "Z:",
+            host_base_path.strip_prefix("/").unwrap_or(host_base_path),
+        ),
+    };
+
+    let mut windows_path = drive.to_string();
+    for component in relative.components() {
+        windows_path.push('\\');
+        windows_path.push_str(&component.as_os_str().to_string_lossy());
+    }
+
+    if relative.as_os_str().is_empty() {
+        windows_path.push('\\');
+    }
+
+    windows_path
+}
+
+// the workshop ids in options, and the ones in the preset. ids can repeat
+fn wanted_workshop_ids(options: &Options) -> Result<Vec<u64>, Error> {
+    let mut ids = options.workshop.to_vec();
+
+    if let Some(path) = options.workshop_preset {
+        let html = std::fs::read(path).context(ReadPresetSnafu { path })?;
+        let preset =
+            preset::parse(&String::from_utf8_lossy(&html)).context(PresetSnafu { path })?;
+
+        ids.extend(preset.mods.iter().filter_map(|r#mod| r#mod.published_id));
+    }
+
+    Ok(ids)
+}
+
+// finds the downloaded workshop items. the ones storage already has a copy of, going by the id in
+// their meta.cpp, are skipped
+fn workshop_items(
+    base_path: &Path,
+    arma: Result<&steam::Arma, String>,
+    mods: &[&mod_cache::Mod],
+    ids: &[u64],
+) -> Result<Vec<(u64, PathBuf)>, Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let arma = arma.map_err(|reason| Error::NoSteamLibrary { reason })?;
+    let loaded: Vec<(u64, &str)> = mods
+        .iter()
+        .filter_map(|r#mod| {
+            let info = ModInfo::read(&base_path.join(&r#mod.name)).ok()?;
+
+            info.published_id.map(|id| (id, r#mod.name.as_str()))
+        })
+        .collect();
+
+    let mut items: Vec<(u64, PathBuf)> = Vec::new();
+
+    for &id in ids {
+        if items.iter().any(|(seen, _)| *seen == id) {
+            continue;
+        }
+
+        if let Some((_, name)) = loaded.iter().find(|(loaded_id, _)| *loaded_id == id) {
+            println!("workshop item {id} is already loaded from storage as {name}");
+            continue;
+        }
+
+        let path = arma.workshop_item(id);
+        ensure!(
+            path.is_dir(),
+            WorkshopItemMissingSnafu {
+                published_id: id,
+                path
+            }
+        );
+
+        items.push((id, path));
+    }
 
-    Ok(Path::new("c:/").join(relative))
+    Ok(items)
 }
 
-pub fn launch(base_path: &Path) -> Result<(), Error> {
+// returns the game's exit code when it was started directly, steam doesn't tell us
+pub fn launch(base_path: &Path, options: &Options) -> Result<i32, Error> {
     let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
 
-    let proton_base_path = convert_host_base_path_to_proton_base_path(base_path)?;
+    let mods = load_order(base_path, &mod_cache, options.repo_name, options.profile)?;
 
-    let binding = generate_mod_args(&proton_base_path, &mod_cache);
-    let cmdline =
-        percent_encoding::utf8_percent_encode(&binding, percent_encoding::NON_ALPHANUMERIC);
+    if let Some(repo_name) = options.repo_name {
+        ensure!(
+            !mods.is_empty(),
+            NoModsForRepoSnafu {
+                repo_name: repo_name.to_string()
+            }
+        );
+    }
+
+    let arma = steam::find_arma_in(options.steam_root);
+
+    let workshop_ids = wanted_workshop_ids(options)?;
+    let workshop = workshop_items(
+        base_path,
+        arma.as_ref().map_err(ToString::to_string),
+        &mods,
+        &workshop_ids,
+    )?;
+
+    if let Some(ignore) = options.deps_ignore {
+        let mut mod_dirs = check::mod_dirs(base_path, &mods);
+        mod_dirs.extend(
+            workshop
+                .iter()
+                .map(|(id, path)| (id.to_string(), path.clone())),
+        );
+
+        let count = check::deps_for_mods(&mod_dirs, ignore);
+        ensure!(count == 0, MissingDependenciesSnafu { count });
+    }
+
+    // the game doesn't know our working directory
+    let absolute_base_path = std::path::absolute(base_path).context(IoSnafu)?;
+    let platform = options
+        .platform
+        .unwrap_or_else(|| Platform::detect(arma.as_ref().ok()));
+
+    let drive_c = match (&arma, platform) {
+        (Ok(arma), Platform::Proton) => arma.drive_c(),
+        (Err(e), Platform::Proton) => {
+            println!("{e}, guessing the proton prefix from the mod path");
+            None
+        }
+        _ => None,
+    };
+    let game_path = |host_path: &Path| match platform {
+        Platform::Windows | Platform::Native => host_path.display().to_string(),
+        Platform::Proton => {
+            convert_host_base_path_to_proton_base_path(host_path, drive_c.as_deref())
+        }
+    };
+
+    let game_base_path = game_path(&absolute_base_path);
+
+    let workshop_paths: Vec<_> = workshop.iter().map(|(_, path)| game_path(path)).collect();
 
-    let steam_url = format!("steam://run/107410//{cmdline}/");
+    let mut args = generate_mod_args(platform, &game_base_path, &mods, &workshop_paths);
 
-    dbg!(&steam_url);
+    if options.dry_run {
+        println!("platform: {platform:?}");
+        println!("storage path: {}", absolute_base_path.display());
+        println!("as seen by the game: {game_base_path}");
+        println!("mods, in load order:");
+        for r#mod in &mods {
+            println!("  {}", r#mod.name);
+        }
+        if !workshop.is_empty() {
+            println!("workshop items, loaded after them:");
+            for ((id, _), path) in workshop.iter().zip(&workshop_paths) {
+                println!("  {id}: {path}");
+            }
+        }
+        println!("arguments:");
+        for arg in &args {
+            println!("  {arg}");
+        }
+    }
+
+    if options.par_file {
+        let par_path = absolute_base_path.join(PAR_FILE_NAME);
+        let (command_line, contents) = split_par_file(args, platform, &game_base_path);
+
+        if options.dry_run {
+            println!("parameter file {}:", par_path.display());
+            print!("{}", contents.replace("\r\n", "\n"));
+        } else {
+            std::fs::write(par_path, contents).context(IoSnafu)?;
+        }
+
+        args = command_line;
+    }
+
+    match options.backend {
+        Backend::Steam => {
+            let url = steam_url(&args);
 
-    open::that(steam_url).unwrap();
+            if options.dry_run {
+                println!("url: {url}");
+                return Ok(0);
+            }
 
-    Ok(())
+            open::that(&url).context(OpenUrlSnafu { url })?;
+
+            Ok(0)
+        }
+        Backend::Direct => {
+            let arma = arma.context(ArmaNotFoundSnafu)?;
+            let mut command =
+                direct_command(platform, &arma, options.proton, options.wrapper, &args)?;
+
+            if options.dry_run {
+                println!("working directory: {}", arma.install_dir.display());
+                println!("command: {}", describe_command(&command));
+                return Ok(0);
+            }
+
+            let program = command.get_program().to_string_lossy().to_string();
+            let status = command.status().context(SpawnSnafu { program })?;
+
+            // killed by a signal if there's no code
+            Ok(status.code().unwrap_or(1))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::commands::gen_srf::gen_srf;
+    use std::path::PathBuf;
 
     #[test]
     #[cfg(windows)]
     fn test_proton_path_conversion() {
         // on windows, this should do nothing
         let original_path = PathBuf::from("C:\\random\\paths\\drive_c\\banana_repo");
-        let converted = convert_host_base_path_to_proton_base_path(&original_path).unwrap();
+        let converted = convert_host_base_path_to_proton_base_path(&original_path, None);
 
-        assert_eq!(original_path, converted);
+        assert_eq!(converted, "C:\\random\\paths\\drive_c\\banana_repo");
     }
 
     #[test]
     #[cfg(not(windows))]
     fn test_proton_path_conversion() {
-        // on windows, this should do nothing
         let original_path = PathBuf::from("/home/random/paths/drive_c/banana_repo");
-        let converted = convert_host_base_path_to_proton_base_path(&original_path).unwrap();
+        let converted = convert_host_base_path_to_proton_base_path(&original_path, None);
+
+        assert_eq!(converted, "C:\\banana_repo");
+
+        let drive_c = PathBuf::from("/home/random/paths/drive_c");
+        assert_eq!(
+            convert_host_base_path_to_proton_base_path(&drive_c, None),
+            "C:\\"
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_z_drive_path_conversion() {
+        let original_path = PathBuf::from("/mnt/games/arma mods/");
+        let converted = convert_host_base_path_to_proton_base_path(&original_path, None);
+
+        assert_eq!(converted, "Z:\\mnt\\games\\arma mods");
+
+        // a drive_c that doesn't belong to arma's prefix is just another directory
+        let arma_drive_c = PathBuf::from("/home/random/compatdata/107410/pfx/drive_c");
+        let other_prefix = PathBuf::from("/home/random/other/drive_c/mods");
+        assert_eq!(
+            convert_host_base_path_to_proton_base_path(&other_prefix, Some(&arma_drive_c)),
+            "Z:\\home\\random\\other\\drive_c\\mods"
+        );
+        assert_eq!(
+            convert_host_base_path_to_proton_base_path(
+                &arma_drive_c.join("mods"),
+                Some(&arma_drive_c)
+            ),
+            "C:\\mods"
+        );
+    }
+
+    fn ace() -> mod_cache::Mod {
+        mod_cache::Mod {
+            name: "@ace".to_string(),
+            repos: Default::default(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn disabled_mods_test() {
+        let base = tempfile::tempdir().unwrap();
+        let mut mod_cache = ModCache::new_empty();
+
+        for (i, (name, enabled)) in [("@ace", true), ("@jsrs", false), ("@local", true)]
+            .into_iter()
+            .enumerate()
+        {
+            mod_cache.mods.insert(
+                crate::md5_digest::Md5Digest::from_bytes([i as u8; 16]),
+                mod_cache::Mod {
+                    name: name.to_string(),
+                    enabled,
+                    ..ace()
+                },
+            );
+        }
+        mod_cache.update_repo_references("alpha", &["@ace", "@jsrs"]);
+
+        let names = |mods: Vec<&mod_cache::Mod>| -> Vec<String> {
+            mods.iter().map(|r#mod| r#mod.name.clone()).collect()
+        };
+
+        // the repository disabled @jsrs, so it isn't loaded unless a profile asks for it
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, None, None).unwrap()),
+            ["@ace", "@local"]
+        );
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, Some("alpha"), None).unwrap()),
+            ["@ace"]
+        );
+
+        std::fs::write(
+            base.path().join("nimble-config.json"),
+            r#"{ "profiles": { "sound": { "mods": ["@jsrs", "@ace"] } } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, None, Some("sound")).unwrap()),
+            ["@jsrs", "@ace"]
+        );
+    }
+
+    #[test]
+    fn load_order_test() {
+        let base = tempfile::tempdir().unwrap();
+        let mut mod_cache = ModCache::new_empty();
+
+        for (i, name) in ["@ace", "@local", "@compat", "@cba_a3", "@tfar"]
+            .into_iter()
+            .enumerate()
+        {
+            mod_cache.mods.insert(
+                crate::md5_digest::Md5Digest::from_bytes([i as u8; 16]),
+                mod_cache::Mod {
+                    name: name.to_string(),
+                    ..ace()
+                },
+            );
+        }
+        mod_cache.update_repo_references("alpha", &["@compat", "@cba_a3", "@ace", "@tfar"]);
+
+        let names = |mods: Vec<&mod_cache::Mod>| -> Vec<String> {
+            mods.iter().map(|r#mod| r#mod.name.clone()).collect()
+        };
+
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, None, None).unwrap()),
+            ["@compat", "@cba_a3", "@ace", "@tfar", "@local"]
+        );
+
+        std::fs::write(
+            base.path().join("nimble-config.json"),
+            r#"{
+                "load_order": { "first": ["@CBA_A3"], "last": ["@compat"] },
+                "profiles": { "light": { "mods": ["@local", "@compat", "@missing", "@ace"] } }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, Some("alpha"), None).unwrap()),
+            ["@cba_a3", "@ace", "@tfar", "@compat"]
+        );
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, None, Some("light")).unwrap()),
+            ["@local", "@ace", "@compat"]
+        );
+        assert!(matches!(
+            load_order(base.path(), &mod_cache, None, Some("heavy")),
+            Err(Error::UnknownProfile { .. })
+        ));
+
+        // gen-srf rescans the mods, but keeps the order the repositories declared
+        for name in ["@ace", "@local", "@compat", "@cba_a3", "@tfar"] {
+            std::fs::create_dir_all(base.path().join(name)).unwrap();
+            std::fs::write(base.path().join(name).join("mod.cpp"), name).unwrap();
+        }
+        gen_srf(base.path());
+
+        let mut mod_cache = ModCache::from_disk(base.path()).unwrap();
+        mod_cache.update_repo_references("alpha", &["@compat", "@cba_a3", "@ace", "@tfar"]);
+        mod_cache.update_repo_references("gone", &["@removed"]);
+        mod_cache.to_disk(base.path()).unwrap();
+
+        gen_srf(base.path());
+
+        let mod_cache = ModCache::from_disk(base.path()).unwrap();
+        assert_eq!(mod_cache.load_orders.keys().collect::<Vec<_>>(), ["alpha"]);
+        assert_eq!(
+            names(load_order(base.path(), &mod_cache, Some("alpha"), None).unwrap()),
+            ["@cba_a3", "@ace", "@tfar", "@compat"]
+        );
+    }
+
+    #[test]
+    fn mod_args() {
+        let args = generate_mod_args(Platform::Proton, "Z:\\mnt\\mods\\", &[&ace()], &[]);
+
+        assert_eq!(args, ["-noLauncher", "-mod=Z:\\mnt\\mods\\@ace;"]);
+        assert_eq!(
+            steam_url(&args),
+            "steam://run/107410//%2DnoLauncher%20%2Dmod%3DZ%3A%5Cmnt%5Cmods%5C%40ace%3B/"
+        );
+    }
+
+    #[test]
+    fn quote_arg_test() {
+        assert_eq!(quote_arg("-noLauncher"), "-noLauncher");
+        assert_eq!(
+            quote_arg("-mod=Z:\\arma mods\\@ace;"),
+            "\"-mod=Z:\\arma mods\\@ace;\""
+        );
+        assert_eq!(quote_arg("C:\\arma mods\\"), "\"C:\\arma mods\\\\\"");
+        assert_eq!(quote_arg("a \\\"b"), "\"a \\\\\\\"b\"");
+        assert_eq!(quote_arg(""), "\"\"");
+    }
+
+    #[test]
+    fn par_file_test() {
+        let args = generate_mod_args(Platform::Proton, "Z:\\mnt\\arma mods", &[&ace()], &[]);
+
+        let (command_line, contents) =
+            split_par_file(args, Platform::Proton, "Z:\\mnt\\arma mods\\");
+
+        assert_eq!(contents, "-mod=Z:\\mnt\\arma mods\\@ace;\r\n");
+        assert_eq!(
+            command_line,
+            ["-noLauncher", "-par=Z:\\mnt\\arma mods\\nimble-launch.par"]
+        );
+
+        // steam only skips the launcher if -noLauncher is on the command line
+        assert_eq!(
+            steam_url(&command_line),
+            "steam://run/107410//%2DnoLauncher%20%22%2Dpar%3DZ%3A%5Cmnt%5Carma%20mods%5Cnimble%2Dlaunch%2Epar%22/"
+        );
+
+        let arma = steam::Arma {
+            steam_root: PathBuf::from("/home/user/.steam/steam"),
+            install_dir: PathBuf::from("/games/steamapps/common/Arma 3"),
+            prefix: None,
+        };
+        let command = direct_command(Platform::Windows, &arma, None, None, &command_line).unwrap();
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-noLauncher", "-par=Z:\\mnt\\arma mods\\nimble-launch.par"]
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn direct_command_test() {
+        let arma = steam::Arma {
+            steam_root: PathBuf::from("/home/user/.steam/steam"),
+            install_dir: PathBuf::from("/games/steamapps/common/Arma 3"),
+            prefix: Some(PathBuf::from("/games/steamapps/compatdata/107410/pfx")),
+        };
+        let args = generate_mod_args(Platform::Proton, "Z:\\mods", &[&ace()], &[]);
+
+        let proton = PathBuf::from("/games/steamapps/common/Proton 9.0/proton");
+        let command = direct_command(Platform::Proton, &arma, Some(&proton), None, &args).unwrap();
+
+        assert_eq!(command.get_program(), proton.as_os_str());
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "run",
+                "/games/steamapps/common/Arma 3/arma3_x64.exe",
+                "-noLauncher",
+                "-mod=Z:\\mods\\@ace;"
+            ]
+        );
+        assert!(command
+            .get_envs()
+            .any(|(key, value)| key == "STEAM_COMPAT_DATA_PATH"
+                && value == Some("/games/steamapps/compatdata/107410".as_ref())));
+        assert_eq!(
+            command.get_current_dir(),
+            Some(Path::new("/games/steamapps/common/Arma 3"))
+        );
+
+        let wrapper = ["umu-run".to_string(), "--verbose".to_string()];
+        let command = direct_command(
+            Platform::Proton,
+            &arma,
+            Some(&proton),
+            Some(&wrapper),
+            &args,
+        )
+        .unwrap();
+        assert_eq!(command.get_program(), "umu-run");
+        assert_eq!(
+            describe_command(&command),
+            "umu-run --verbose '/games/steamapps/common/Arma 3/arma3_x64.exe' -noLauncher '-mod=Z:\\mods\\@ace;'"
+        );
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "--verbose",
+                "/games/steamapps/common/Arma 3/arma3_x64.exe",
+                "-noLauncher",
+                "-mod=Z:\\mods\\@ace;"
+            ]
+        );
+
+        assert!(matches!(
+            direct_command(Platform::Proton, &arma, None, None, &args),
+            Err(Error::NoRunner)
+        ));
+    }
+
+    #[test]
+    fn workshop_test() {
+        let library = tempfile::tempdir().unwrap();
+        let arma = steam::Arma {
+            steam_root: library.path().to_owned(),
+            install_dir: library.path().join("steamapps/common/Arma 3"),
+            prefix: None,
+        };
+        std::fs::create_dir_all(arma.workshop_item(450814997)).unwrap();
+        std::fs::create_dir_all(arma.workshop_item(463939057)).unwrap();
+
+        let base = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(base.path().join("@ace")).unwrap();
+        std::fs::write(
+            base.path().join("@ace").join("meta.cpp"),
+            "publishedid = 463939057;",
+        )
+        .unwrap();
+
+        let items = workshop_items(
+            base.path(),
+            Ok(&arma),
+            &[&ace()],
+            &[450814997, 463939057, 450814997],
+        )
+        .unwrap();
+        assert_eq!(items, [(450814997, arma.workshop_item(450814997))]);
+
+        assert!(matches!(
+            workshop_items(base.path(), Ok(&arma), &[&ace()], &[1]),
+            Err(Error::WorkshopItemMissing {
+                published_id: 1,
+                ..
+            })
+        ));
+        assert!(
+            workshop_items(base.path(), Err("not found".to_string()), &[], &[])
+                .unwrap()
+                .is_empty()
+        );
+
+        let args = generate_mod_args(
+            Platform::Proton,
+            "Z:\\mods",
+            &[&ace()],
+            &["Z:\\workshop\\450814997".to_string()],
+        );
+        assert_eq!(args[1], "-mod=Z:\\mods\\@ace;Z:\\workshop\\450814997;");
+    }
+
+    #[test]
+    fn native_test() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let arma = steam::Arma {
+            steam_root: PathBuf::from("/home/user/.steam/steam"),
+            install_dir: install_dir.path().to_owned(),
+            prefix: None,
+        };
+
+        if !cfg!(windows) {
+            assert_eq!(Platform::detect(Some(&arma)), Platform::Proton);
+            std::fs::write(arma.native_executable(), "").unwrap();
+            assert_eq!(Platform::detect(Some(&arma)), Platform::Native);
+        }
+
+        let args = generate_mod_args(Platform::Native, "/mnt/arma mods/", &[&ace()], &[]);
+        assert_eq!(args, ["-noLauncher", "-mod=/mnt/arma mods/@ace;"]);
+        assert_eq!(
+            par_file_args(Platform::Native, "/mnt/arma mods"),
+            ["-par=/mnt/arma mods/nimble-launch.par"]
+        );
 
-        assert_eq!(converted, PathBuf::from("c:/banana_repo"));
+        let command = direct_command(Platform::Native, &arma, None, None, &args).unwrap();
+        assert_eq!(command.get_program(), arma.native_executable().as_os_str());
+        assert_eq!(command.get_args().count(), 2);
     }
 }
@@ -1,3 +1,10 @@
+pub mod bundle;
+pub mod check;
 pub mod gen_srf;
 pub mod launch;
+pub mod mods;
+pub mod pbo;
+pub mod preset;
+pub mod serve;
+pub mod server;
 pub mod sync;
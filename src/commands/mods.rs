@@ -0,0 +1,194 @@
+use crate::commands::check::is_pbo;
+use crate::commands::gen_srf::open_cache_or_gen_srf;
+use crate::commands::launch::{self, repo_mods};
+use crate::mod_info::ModInfo;
+use crate::{mod_cache, pbo, rap};
+use indicatif::HumanBytes;
+use snafu::{ResultExt, Snafu};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use walkdir::WalkDir;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to open ModCache: {}", source))]
+    ModCacheOpen { source: mod_cache::Error },
+    #[snafu(display("Failed to resolve the load order: {}", source))]
+    LoadOrder { source: launch::Error },
+}
+
+fn mod_size(mod_path: &Path) -> u64 {
+    WalkDir::new(mod_path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn pbo_version(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let config = pbo::Pbo::read(BufReader::new(file))
+        .ok()?
+        .read_config()
+        .ok()??;
+
+    let version = config
+        .class("CfgPatches")?
+        .classes()
+        .find_map(|(_, patch)| patch.value("versionStr").and_then(rap::Value::as_str))
+        .map(str::to_string);
+
+    version
+}
+
+// mod.cpp rarely has a version, but most addons declare theirs in CfgPatches
+fn mod_version(mod_path: &Path, info: &ModInfo) -> Option<String> {
+    if let Some(version) = &info.version {
+        return Some(version.clone());
+    }
+
+    WalkDir::new(mod_path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && is_pbo(entry.path()))
+        .find_map(|entry| pbo_version(entry.path()))
+}
+
+// folder, name, workshop id, version, size and whether it's enabled, for every mod in the repo
+fn list_rows(
+    base_path: &Path,
+    mod_cache: &mod_cache::ModCache,
+    repo_name: Option<&str>,
+) -> Vec<[String; 6]> {
+    let mut mods = repo_mods(mod_cache, repo_name);
+    mods.sort_by_key(|r#mod| r#mod.name.to_lowercase());
+
+    mods.into_iter()
+        .map(|r#mod| {
+            let mod_path = base_path.join(&r#mod.name);
+            let info = ModInfo::read(&mod_path).unwrap_or_else(|e| {
+                println!("{}: failed to read mod info: {e}", r#mod.name);
+                ModInfo::default()
+            });
+
+            [
+                r#mod.name.clone(),
+                info.name.clone().unwrap_or_default(),
+                info.published_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_default(),
+                mod_version(&mod_path, &info).unwrap_or_default(),
+                HumanBytes(mod_size(&mod_path)).to_string(),
+                if r#mod.enabled { "yes" } else { "no" }.to_string(),
+            ]
+        })
+        .collect()
+}
+
+pub fn list(base_path: &Path, repo_name: Option<&str>) -> Result<(), Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let rows = list_rows(base_path, &mod_cache, repo_name);
+
+    let header = [
+        "FOLDER",
+        "NAME",
+        "WORKSHOP ID",
+        "VERSION",
+        "SIZE",
+        "ENABLED",
+    ]
+    .map(String::from);
+    let mut widths = header.clone().map(|column| column.chars().count());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect();
+
+        println!("{}", line.join("  ").trim_end());
+    }
+
+    Ok(())
+}
+
+// prints the mods launch would load, in the order it would load them
+pub fn order(
+    base_path: &Path,
+    repo_name: Option<&str>,
+    profile: Option<&str>,
+) -> Result<(), Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let mods =
+        launch::load_order(base_path, &mod_cache, repo_name, profile).context(LoadOrderSnafu)?;
+
+    let width = mods.len().to_string().len();
+    for (i, r#mod) in mods.iter().enumerate() {
+        println!("{:>width$}  {}", i + 1, r#mod.name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gen_srf::gen_srf;
+
+    #[test]
+    fn list_test() {
+        let base = tempfile::tempdir().unwrap();
+
+        let ace = base.path().join("@ace");
+        std::fs::create_dir_all(&ace).unwrap();
+        std::fs::write(
+            ace.join("mod.cpp"),
+            "name = \"Advanced Combat Environment\";\nversion = \"3.15.2\";\n",
+        )
+        .unwrap();
+        std::fs::write(
+            ace.join("meta.cpp"),
+            "protocol = 1;\npublishedid = 463939057;\nname = \"ace\";\n",
+        )
+        .unwrap();
+
+        let jsrs = base.path().join("@jsrs");
+        std::fs::create_dir_all(&jsrs).unwrap();
+        std::fs::write(jsrs.join("readme.txt"), "no mod.cpp here").unwrap();
+
+        gen_srf(base.path());
+
+        let mut mod_cache = mod_cache::ModCache::from_disk(base.path()).unwrap();
+        for r#mod in mod_cache.mods.values_mut() {
+            r#mod.enabled = r#mod.name != "@jsrs";
+        }
+
+        let rows = list_rows(base.path(), &mod_cache, None);
+        let columns = |row: &[String; 6]| {
+            [
+                row[0].clone(),
+                row[1].clone(),
+                row[2].clone(),
+                row[5].clone(),
+            ]
+        };
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            columns(&rows[0]),
+            ["@ace", "Advanced Combat Environment", "463939057", "yes"]
+        );
+        assert_eq!(rows[0][3], "3.15.2");
+        assert_eq!(columns(&rows[1]), ["@jsrs", "", "", "no"]);
+    }
+}
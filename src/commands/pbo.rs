@@ -0,0 +1,252 @@
+use crate::pbo::{self, Pbo, PboWriter};
+use crate::rap;
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("failed to read pbo: {}", source))]
+    PboRead { source: pbo::Error },
+    #[snafu(display("failed to write pbo: {}", source))]
+    PboWrite { source: pbo::Error },
+    #[snafu(display("refusing to extract {} outside of the output directory", name))]
+    UnsafeEntryName { name: String },
+    #[snafu(display("{} has no config.bin", path.display()))]
+    MissingConfig { path: PathBuf },
+}
+
+// pbo entry names are relative windows paths, turn them into host paths without letting them escape
+fn entry_path(out: &Path, name: &str) -> Result<PathBuf, Error> {
+    let mut path = out.to_owned();
+
+    for component in name.split(['\\', '/']).filter(|c| !c.is_empty()) {
+        if component == "." || component == ".." || component.contains(':') {
+            return UnsafeEntryNameSnafu { name }.fail();
+        }
+
+        path.push(component);
+    }
+
+    Ok(path)
+}
+
+fn write_entry(out: &Path, name: &str, data: &[u8]) -> Result<(), Error> {
+    let path = entry_path(out, name)?;
+
+    std::fs::create_dir_all(path.parent().expect("entry path did not have a parent"))
+        .context(IoSnafu)?;
+    std::fs::write(&path, data).context(IoSnafu)
+}
+
+// extracts the named entries, or everything if no names are given
+pub fn extract(pbo_path: &Path, out: &Path, names: &[String]) -> Result<(), Error> {
+    let file = File::open(pbo_path).context(IoSnafu)?;
+    let mut pbo = Pbo::read(BufReader::new(file)).context(PboReadSnafu)?;
+
+    if !names.is_empty() {
+        for name in names {
+            let data = pbo.read_entry(name).context(PboReadSnafu)?;
+            write_entry(out, name, &data)?;
+        }
+
+        return Ok(());
+    }
+
+    for index in 0..pbo.entries.len() {
+        let name = pbo.entries[index].filename.clone();
+
+        // the first entry only carries the header extensions
+        if name.is_empty() {
+            continue;
+        }
+
+        let data = pbo.read_entry_at(index).context(PboReadSnafu)?;
+        write_entry(out, &name, &data)?;
+    }
+
+    // keep the prefix around the same way other tools do, so the directory can be packed again
+    if let Some(prefix) = pbo.prefix() {
+        if pbo.entry_index("$PBOPREFIX$").is_none() {
+            std::fs::write(out.join("$PBOPREFIX$"), prefix).context(IoSnafu)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn pack(dir: &Path, out: &Path, prefix: Option<&str>) -> Result<(), Error> {
+    let mut writer = PboWriter::new();
+
+    if let Some(prefix) = prefix {
+        writer.extension("prefix", prefix);
+    }
+
+    writer.add_dir(dir).context(PboWriteSnafu)?;
+
+    let file = File::create(out).context(IoSnafu)?;
+    writer.write(BufWriter::new(file)).context(PboWriteSnafu)
+}
+
+fn format_list(values: &[String]) -> String {
+    if values.is_empty() {
+        return "(none)".to_string();
+    }
+
+    values.join(", ")
+}
+
+pub fn config(pbo_path: &Path) -> Result<(), Error> {
+    let file = File::open(pbo_path).context(IoSnafu)?;
+    let mut pbo = Pbo::read(BufReader::new(file)).context(PboReadSnafu)?;
+    let config = pbo
+        .read_config()
+        .context(PboReadSnafu)?
+        .context(MissingConfigSnafu { path: pbo_path })?;
+
+    for patch in rap::cfg_patches(&config) {
+        println!("{}", patch.name);
+
+        if let Some(version) = patch.required_version {
+            println!("    requiredVersion: {version}");
+        }
+
+        println!(
+            "    requiredAddons: {}",
+            format_list(&patch.required_addons)
+        );
+        println!("    units: {}", format_list(&patch.units));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_test() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let pbo_path: PathBuf = [
+            project_root,
+            "test_files",
+            "@ace",
+            "addons",
+            "ace_advanced_ballistics.pbo",
+        ]
+        .iter()
+        .collect();
+
+        let out = tempfile::tempdir().unwrap();
+        extract(&pbo_path, out.path(), &[]).unwrap();
+
+        let stringtable = std::fs::metadata(out.path().join("stringtable.xml")).unwrap();
+        assert_eq!(stringtable.len(), 26145);
+
+        let protractor = std::fs::metadata(out.path().join("UI").join("protractor.paa")).unwrap();
+        assert_eq!(protractor.len(), 41318);
+
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("$PBOPREFIX$")).unwrap(),
+            "z\\ace\\addons\\advanced_ballistics"
+        );
+    }
+
+    #[test]
+    fn extract_single_entry_test() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let pbo_path: PathBuf = [
+            project_root,
+            "test_files",
+            "@ace",
+            "addons",
+            "ace_advanced_ballistics.pbo",
+        ]
+        .iter()
+        .collect();
+
+        let out = tempfile::tempdir().unwrap();
+        extract(&pbo_path, out.path(), &["CONFIG.BIN".to_string()]).unwrap();
+
+        assert_eq!(std::fs::read_dir(out.path()).unwrap().count(), 1);
+        assert_eq!(
+            std::fs::metadata(out.path().join("CONFIG.BIN"))
+                .unwrap()
+                .len(),
+            4109
+        );
+    }
+
+    #[test]
+    fn extract_then_pack_test() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let mod_path: PathBuf = [project_root, "test_files", "@ace"].iter().collect();
+        let pbo_path = mod_path.join("addons").join("ace_advanced_ballistics.pbo");
+
+        let out = tempfile::tempdir().unwrap();
+        let extracted = out.path().join("extracted");
+        extract(&pbo_path, &extracted, &[]).unwrap();
+
+        let packed_path = out.path().join("addons").join("packed.pbo");
+        std::fs::create_dir_all(packed_path.parent().unwrap()).unwrap();
+        pack(&extracted, &packed_path, None).unwrap();
+
+        let packed = Pbo::read(BufReader::new(File::open(&packed_path).unwrap())).unwrap();
+        assert_eq!(
+            packed.extensions["prefix"],
+            "z\\ace\\addons\\advanced_ballistics"
+        );
+
+        // only the header differs, as timestamps and the extra extensions aren't preserved
+        let entry_parts = |file: &crate::srf::File| {
+            file.parts[1..file.parts.len() - 1]
+                .iter()
+                .map(|part| (part.path.clone(), part.length, part.checksum.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let original = crate::srf::scan_pbo(&pbo_path, &mod_path).unwrap();
+        let repacked = crate::srf::scan_pbo(&packed_path, out.path()).unwrap();
+        assert_eq!(original.parts.len(), repacked.parts.len());
+        assert_eq!(entry_parts(&original), entry_parts(&repacked));
+    }
+
+    #[test]
+    fn extract_uppercase_prefix_test() {
+        let out = tempfile::tempdir().unwrap();
+        let pbo_path = out.path().join("upper.pbo");
+
+        let mut writer = PboWriter::new();
+        writer.extension("PREFIX", "x\\upper");
+        writer
+            .add_data("config.cpp", b"class CfgPatches {};".to_vec(), 0)
+            .unwrap();
+        writer
+            .write(BufWriter::new(File::create(&pbo_path).unwrap()))
+            .unwrap();
+
+        let extracted = out.path().join("extracted");
+        extract(&pbo_path, &extracted, &[]).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(extracted.join("$PBOPREFIX$")).unwrap(),
+            "x\\upper"
+        );
+    }
+
+    #[test]
+    fn entry_names_cannot_escape() {
+        let out = Path::new("out");
+
+        assert_eq!(
+            entry_path(out, "functions\\fnc_test.sqf").unwrap(),
+            out.join("functions").join("fnc_test.sqf")
+        );
+        assert!(entry_path(out, "..\\..\\evil.dll").is_err());
+        assert!(entry_path(out, "C:\\evil.dll").is_err());
+    }
+}
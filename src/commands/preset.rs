@@ -0,0 +1,271 @@
+use crate::commands::gen_srf::open_cache_or_gen_srf;
+use crate::commands::launch;
+use crate::config::{self, Config, Profile};
+use crate::mod_cache;
+use crate::mod_info::ModInfo;
+use crate::preset::{self, Preset, PresetMod};
+use snafu::{ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error on {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Failed to open ModCache: {}", source))]
+    ModCacheOpen { source: mod_cache::Error },
+    #[snafu(display("failed to parse {}: {}", path.display(), source))]
+    Preset {
+        path: PathBuf,
+        source: preset::Error,
+    },
+    #[snafu(display("config error: {}", source))]
+    Config { source: config::Error },
+    #[snafu(display("Failed to resolve the load order: {}", source))]
+    LoadOrder { source: launch::Error },
+}
+
+struct LocalMod {
+    name: String,
+    published_id: Option<u64>,
+}
+
+fn read_info(base_path: &Path, r#mod: &mod_cache::Mod) -> ModInfo {
+    ModInfo::read(&base_path.join(&r#mod.name)).unwrap_or_else(|e| {
+        println!("{}: failed to read mod info: {e}", r#mod.name);
+        ModInfo::default()
+    })
+}
+
+fn local_mods(base_path: &Path, mod_cache: &mod_cache::ModCache) -> Vec<LocalMod> {
+    let mut mods: Vec<_> = mod_cache
+        .mods
+        .values()
+        .map(|r#mod| {
+            let info = read_info(base_path, r#mod);
+
+            LocalMod {
+                name: r#mod.name.clone(),
+                published_id: info.published_id,
+            }
+        })
+        .collect();
+
+    mods.sort_by(|a, b| a.name.cmp(&b.name));
+    mods
+}
+
+fn find_named<'a>(local_mods: &'a [LocalMod], name: &str) -> Option<&'a LocalMod> {
+    local_mods
+        .iter()
+        .find(|local| local.name.eq_ignore_ascii_case(name))
+}
+
+// workshop mods are matched by their id, since nobody keeps the workshop's folder names.
+// everything else by folder name, which the launcher may or may not have recorded
+fn find_local<'a>(local_mods: &'a [LocalMod], preset_mod: &PresetMod) -> Option<&'a LocalMod> {
+    preset_mod
+        .published_id
+        .and_then(|id| {
+            local_mods
+                .iter()
+                .find(|local| local.published_id == Some(id))
+        })
+        .or_else(|| find_named(local_mods, preset_mod.local_name.as_deref()?))
+        .or_else(|| find_named(local_mods, &format!("@{}", preset_mod.display_name)))
+}
+
+// turns a launcher preset into a profile with the matching mods in storage. returns how many mods
+// of the preset are missing
+pub fn import(base_path: &Path, file: &Path, name: Option<&str>) -> Result<usize, Error> {
+    let html = std::fs::read(file).context(IoSnafu { path: file })?;
+    let preset =
+        preset::parse(&String::from_utf8_lossy(&html)).context(PresetSnafu { path: file })?;
+
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let local_mods = local_mods(base_path, &mod_cache);
+
+    let mut mods: Vec<String> = Vec::new();
+    let mut missing = 0;
+
+    for preset_mod in &preset.mods {
+        match find_local(&local_mods, preset_mod) {
+            Some(local) if mods.contains(&local.name) => {}
+            Some(local) => mods.push(local.name.clone()),
+            None => {
+                missing += 1;
+
+                match preset_mod.published_id {
+                    Some(id) => println!(
+                        "{} (workshop id {id}) is not in storage",
+                        preset_mod.display_name
+                    ),
+                    None => println!("{} is not in storage", preset_mod.display_name),
+                }
+            }
+        }
+    }
+
+    let file_stem = file.file_stem().map(|stem| stem.to_string_lossy());
+    let name = name
+        .map(str::to_string)
+        .or(preset.name)
+        .or(file_stem.map(|stem| stem.to_string()))
+        .unwrap_or_else(|| "preset".to_string());
+
+    let mut config = Config::from_disk_or_default(base_path).context(ConfigSnafu)?;
+    let count = mods.len();
+
+    if config
+        .profiles
+        .insert(name.clone(), Profile { mods })
+        .is_some()
+    {
+        println!("replacing the existing profile {name}");
+    }
+
+    config.to_disk(base_path).context(ConfigSnafu)?;
+
+    println!("profile {name} loads {count} mods, {missing} are missing");
+
+    Ok(missing)
+}
+
+// writes the mods launch would load as a launcher preset. mods with a workshop id are listed as
+// workshop mods, so the launcher can download them, the rest as local mods
+pub fn export(
+    base_path: &Path,
+    out: &Path,
+    repo_name: Option<&str>,
+    profile: Option<&str>,
+    name: Option<&str>,
+) -> Result<(), Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    let mods =
+        launch::load_order(base_path, &mod_cache, repo_name, profile).context(LoadOrderSnafu)?;
+
+    let mods: Vec<_> = mods
+        .into_iter()
+        .map(|r#mod| {
+            let info = read_info(base_path, r#mod);
+
+            PresetMod {
+                display_name: info.name.unwrap_or_else(|| r#mod.name.clone()),
+                published_id: info.published_id,
+                local_name: info.published_id.is_none().then(|| r#mod.name.clone()),
+            }
+        })
+        .collect();
+
+    let workshop = mods
+        .iter()
+        .filter(|r#mod| r#mod.published_id.is_some())
+        .count();
+    let preset = Preset {
+        name: Some(
+            name.or(profile)
+                .or(repo_name)
+                .unwrap_or("Nimble")
+                .to_string(),
+        ),
+        mods,
+    };
+
+    std::fs::write(out, preset::to_html(&preset)).context(IoSnafu { path: out })?;
+
+    println!(
+        "exported {} mods to {}, {workshop} of them from the workshop",
+        preset.mods.len(),
+        out.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gen_srf::gen_srf;
+
+    #[test]
+    fn import_test() {
+        let base = tempfile::tempdir().unwrap();
+
+        // workshop folder names are whatever the user chose
+        let cba = base.path().join("@cba");
+        std::fs::create_dir_all(&cba).unwrap();
+        std::fs::write(cba.join("meta.cpp"), "publishedid = 450814997;").unwrap();
+
+        let compat = base.path().join("@unit_compat");
+        std::fs::create_dir_all(&compat).unwrap();
+        std::fs::write(compat.join("mod.cpp"), "name = \"Unit Compat\";").unwrap();
+
+        let other = base.path().join("@other");
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join("mod.cpp"), "name = \"Other\";").unwrap();
+
+        gen_srf(base.path());
+
+        let preset: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "test_files",
+            "presets",
+            "operation.html",
+        ]
+        .iter()
+        .collect();
+
+        let missing = import(base.path(), &preset, None).unwrap();
+        assert_eq!(missing, 1);
+
+        let config = Config::from_disk_or_default(base.path()).unwrap();
+        assert_eq!(
+            config.profiles["Operation & Training"].mods,
+            ["@cba", "@unit_compat"]
+        );
+    }
+
+    #[test]
+    fn export_test() {
+        let base = tempfile::tempdir().unwrap();
+
+        let cba = base.path().join("@cba");
+        std::fs::create_dir_all(&cba).unwrap();
+        std::fs::write(cba.join("meta.cpp"), "publishedid = 450814997;").unwrap();
+
+        let compat = base.path().join("@unit_compat");
+        std::fs::create_dir_all(&compat).unwrap();
+        std::fs::write(compat.join("mod.cpp"), "name = \"Unit Compat\";").unwrap();
+
+        gen_srf(base.path());
+
+        std::fs::write(
+            base.path().join("nimble-config.json"),
+            r#"{ "profiles": { "Operation & Training": { "mods": ["@cba", "@unit_compat"] } } }"#,
+        )
+        .unwrap();
+
+        let out = base.path().join("exported.html");
+        export(base.path(), &out, None, Some("Operation & Training"), None).unwrap();
+
+        let exported = preset::parse(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(exported.name.as_deref(), Some("Operation & Training"));
+        assert_eq!(
+            exported.mods,
+            [
+                PresetMod {
+                    display_name: "@cba".to_string(),
+                    published_id: Some(450814997),
+                    local_name: None,
+                },
+                PresetMod {
+                    display_name: "Unit Compat".to_string(),
+                    published_id: None,
+                    local_name: Some("@unit_compat".to_string()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::repository::{self, Repository};
+use crate::srf;
+use md5::{Digest, Md5};
+use percent_encoding::percent_decode_str;
+use rayon::prelude::*;
+use snafu::{ResultExt, Snafu};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use walkdir::WalkDir;
+
+const WORKER_COUNT: usize = 8;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to serialize repository info: {}", source))]
+    RepositorySerialization { source: serde_json::Error },
+    #[snafu(display("Failed to generate srf for {}: {}", path.display(), source))]
+    SrfGeneration { path: PathBuf, source: srf::Error },
+    #[snafu(display("Failed to write {}: {}", path.display(), source))]
+    SrfWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Failed to serialize srf: {}", source))]
+    SrfSerialization { source: serde_json::Error },
+    #[snafu(display("Failed to listen on port {}: {}", port, source))]
+    Bind {
+        port: u16,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+struct ServedRepo {
+    repo_json: Vec<u8>,
+    // lowercase mod name to folder on disk, as clients may request them with a different case
+    mod_folders: HashMap<String, PathBuf>,
+}
+
+// like gen_srf_for_mod, but a mod that can't be scanned shouldn't take the whole server down
+fn write_srf(mod_path: &Path) -> Result<srf::Mod, Error> {
+    let generated_srf = srf::scan_mod(mod_path).context(SrfGenerationSnafu { path: mod_path })?;
+
+    let path = mod_path.join("mod.srf");
+    let file = File::create(&path).context(SrfWriteSnafu { path })?;
+    serde_json::to_writer(BufWriter::new(file), &generated_srf).context(SrfSerializationSnafu)?;
+
+    Ok(generated_srf)
+}
+
+fn generate_repository(repo_name: &str, srfs: &[srf::Mod]) -> Repository {
+    let mut mods: Vec<_> = srfs
+        .iter()
+        .map(|srf| repository::Mod {
+            mod_name: srf.name.clone(),
+            checksum: srf.checksum.clone(),
+            enabled: true,
+        })
+        .collect();
+
+    mods.sort_by(|a, b| a.mod_name.cmp(&b.mod_name));
+
+    // swifty includes a timestamp in this checksum and nobody compares it, so just make it change
+    // whenever a mod does
+    let checksum = {
+        let mut hasher = Md5::new();
+
+        for r#mod in &mods {
+            hasher.update(r#mod.checksum.as_bytes());
+        }
+
+        format!("{:X}", hasher.finalize())
+    };
+
+    Repository {
+        repo_name: repo_name.to_string(),
+        checksum,
+        required_mods: mods,
+        optional_mods: vec![],
+        client_parameters: String::new(),
+        repo_basic_authentication: None,
+        version: "1".to_string(),
+        servers: vec![],
+    }
+}
+
+// only single ranges are supported, which is all swifty clients ask for. returns an inclusive range
+fn parse_range(header: &str, length: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+
+    if spec.contains(',') || length == 0 {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+
+    let (start, end) = match (start.is_empty(), end.is_empty()) {
+        (true, false) => {
+            let suffix: u64 = end.parse().ok()?;
+            (length.checked_sub(suffix.min(length))?, length - 1)
+        }
+        (false, true) => (start.parse().ok()?, length - 1),
+        (false, false) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(length - 1),
+        ),
+        (true, true) => return None,
+    };
+
+    (start <= end && start < length).then_some((start, end))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("invalid header")
+}
+
+impl ServedRepo {
+    fn load(base_path: &Path, repo_name: &str) -> Result<Self, Error> {
+        let mod_folders: HashMap<_, _> = WalkDir::new(base_path)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_dir() && e.file_name().to_string_lossy().starts_with('@'))
+            .map(|e| {
+                (
+                    e.file_name().to_string_lossy().to_lowercase(),
+                    e.into_path(),
+                )
+            })
+            .collect();
+
+        // clients diff against mod.srf and compare its checksum with repo.json, so both come from
+        // the same scan of what's on disk
+        let srfs = mod_folders
+            .par_iter()
+            .map(|(_, path)| write_srf(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let repository = generate_repository(repo_name, &srfs);
+        let repo_json = serde_json::to_vec(&repository).context(RepositorySerializationSnafu)?;
+
+        Ok(Self {
+            repo_json,
+            mod_folders,
+        })
+    }
+
+    fn resolve_path(&self, url: &str) -> Option<PathBuf> {
+        let path = url.split('?').next()?;
+        let decoded = percent_decode_str(path).decode_utf8().ok()?;
+
+        let mut components = decoded.split('/').filter(|c| !c.is_empty());
+        let mut path = self
+            .mod_folders
+            .get(&components.next()?.to_lowercase())?
+            .clone();
+
+        for component in components {
+            // don't let anyone walk out of the mod directory
+            if component == "." || component == ".." || component.contains(['\\', ':']) {
+                return None;
+            }
+
+            path.push(component);
+        }
+
+        Some(path)
+    }
+
+    fn respond(&self, request: Request) -> std::io::Result<()> {
+        if !matches!(request.method(), Method::Get | Method::Head) {
+            return request.respond(Response::empty(StatusCode(405)));
+        }
+
+        if request.url() == "/repo.json" {
+            let response = Response::from_data(self.repo_json.clone())
+                .with_header(header("Content-Type", "application/json"));
+
+            return request.respond(response);
+        }
+
+        let file = self
+            .resolve_path(request.url())
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| {
+                let metadata = file.metadata().ok()?;
+                metadata.is_file().then_some((metadata.len(), file))
+            });
+
+        let Some((length, mut file)) = file else {
+            return request.respond(Response::empty(StatusCode(404)));
+        };
+
+        let range = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Range"))
+            .map(|h| parse_range(h.value.as_str(), length));
+
+        match range {
+            None => {
+                let response = Response::new(
+                    StatusCode(200),
+                    vec![header("Accept-Ranges", "bytes")],
+                    file,
+                    Some(length as usize),
+                    None,
+                );
+
+                request.respond(response)
+            }
+            Some(Some((start, end))) => {
+                file.seek(SeekFrom::Start(start))?;
+                let range_length = end - start + 1;
+
+                let response = Response::new(
+                    StatusCode(206),
+                    vec![
+                        header("Accept-Ranges", "bytes"),
+                        header("Content-Range", &format!("bytes {start}-{end}/{length}")),
+                    ],
+                    file.take(range_length),
+                    Some(range_length as usize),
+                    None,
+                );
+
+                request.respond(response)
+            }
+            Some(None) => {
+                let response = Response::empty(StatusCode(416))
+                    .with_header(header("Content-Range", &format!("bytes */{length}")));
+
+                request.respond(response)
+            }
+        }
+    }
+}
+
+fn run(server: &Server, repo: &ServedRepo) {
+    std::thread::scope(|scope| {
+        for _ in 0..WORKER_COUNT {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    let url = request.url().to_string();
+
+                    if let Err(e) = repo.respond(request) {
+                        println!("failed to respond to {url}: {e}");
+                    }
+                }
+            });
+        }
+    });
+}
+
+pub fn serve(base_path: &Path, port: u16, repo_name: Option<&str>) -> Result<(), Error> {
+    let repo_name = repo_name.map_or_else(
+        || {
+            base_path
+                .file_name()
+                .map_or_else(|| "nimble".into(), |name| name.to_string_lossy())
+        },
+        Into::into,
+    );
+
+    let repo = ServedRepo::load(base_path, &repo_name)?;
+    let server = Server::http(("0.0.0.0", port)).context(BindSnafu { port })?;
+
+    println!(
+        "serving {} as {repo_name} on port {port}",
+        base_path.display()
+    );
+
+    run(&server, &repo);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gen_srf::gen_srf;
+    use crate::commands::sync;
+    use crate::md5_digest::Md5Digest;
+    use crate::mod_cache::ModCache;
+    use crate::source::Source;
+
+    fn serve_test_mods() -> (tempfile::TempDir, String) {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let ace_path: PathBuf = [project_root, "test_files", "@ace", "addons"]
+            .iter()
+            .collect();
+
+        let served = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(served.path().join("@ace/addons")).unwrap();
+        for entry in std::fs::read_dir(ace_path).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(
+                entry.path(),
+                served.path().join("@ace/addons").join(entry.file_name()),
+            )
+            .unwrap();
+        }
+
+        let repo = ServedRepo::load(served.path(), "served").unwrap();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/",
+            server.server_addr().to_ip().unwrap().port()
+        );
+
+        std::thread::spawn(move || run(&server, &repo));
+
+        (served, url)
+    }
+
+    #[test]
+    fn range_parsing() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("lines=0-1", 1000), None);
+    }
+
+    #[test]
+    fn repo_json_matches_served_srfs() {
+        let served = tempfile::tempdir().unwrap();
+        let mod_path = served.path().join("@mod");
+        std::fs::create_dir_all(&mod_path).unwrap();
+        std::fs::write(mod_path.join("readme.txt"), "old").unwrap();
+
+        // the cache and mod.srf are from before the mod changed
+        gen_srf(served.path());
+        std::fs::write(mod_path.join("readme.txt"), "new").unwrap();
+
+        let repo = ServedRepo::load(served.path(), "served").unwrap();
+        let repository: Repository = serde_json::from_slice(&repo.repo_json).unwrap();
+        let served_srf: srf::Mod =
+            serde_json::from_slice(&std::fs::read(mod_path.join("mod.srf")).unwrap()).unwrap();
+
+        assert_eq!(repository.required_mods[0].checksum, served_srf.checksum);
+        assert_eq!(
+            served_srf.checksum,
+            srf::scan_mod(&mod_path).unwrap().checksum
+        );
+    }
+
+    #[test]
+    fn sync_from_served_directory() {
+        let (_served, url) = serve_test_mods();
+        let agent = ureq::AgentBuilder::new().build();
+
+        let range = agent
+            .get(&format!("{url}@ACE/addons/ace_advanced_ballistics.pbo"))
+            .set("Range", "bytes=-21")
+            .call()
+            .unwrap();
+        assert_eq!(range.status(), 206);
+        assert_eq!(range.header("Content-Length"), Some("21"));
+
+        // encoded so the client doesn't normalize the dots away before sending it
+        let escape = agent
+            .get(&format!("{url}@ace%2F..%2F..%2Fetc%2Fpasswd"))
+            .call();
+        assert!(matches!(escape, Err(ureq::Error::Status(404, _))));
+
+        let target = tempfile::tempdir().unwrap();
+        let source = Source::new(agent, &url).unwrap();
+        sync::sync(&source, target.path(), false, false).unwrap();
+
+        let cache = ModCache::from_disk(target.path()).unwrap();
+        let r#mod = &cache.mods[&Md5Digest::new("787662722D70C36DF28CD1D5EE8D8E86").unwrap()];
+        assert!(r#mod.is_referenced_by("served"));
+    }
+
+    #[test]
+    fn resolve_path_stays_in_mod() {
+        let repo = ServedRepo {
+            repo_json: Vec::new(),
+            mod_folders: HashMap::from([("@ace".to_string(), PathBuf::from("/mods/@ace"))]),
+        };
+
+        assert_eq!(
+            repo.resolve_path("/@ACE/addons/ace_main.pbo?v=1"),
+            Some(PathBuf::from("/mods/@ace/addons/ace_main.pbo"))
+        );
+        assert_eq!(repo.resolve_path("/@cba/addons/cba_main.pbo"), None);
+
+        for url in [
+            "/@ace/../../etc/passwd",
+            "/@ace/%2e%2e/%2e%2e/etc/passwd",
+            "/@ace/./addons/ace_main.pbo",
+            "/@ace/..%5C..%5Cetc%5Cpasswd",
+            "/@ace/addons\\..\\..\\secret",
+            "/@ace/C:%5Cwindows%5Cwin.ini",
+            "/@ace/C:/windows/win.ini",
+        ] {
+            assert_eq!(repo.resolve_path(url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn load_reports_srf_errors() {
+        let served = tempfile::tempdir().unwrap();
+        // mod.srf can't be written over a directory
+        std::fs::create_dir_all(served.path().join("@ace/mod.srf")).unwrap();
+
+        let result = ServedRepo::load(served.path(), "served");
+
+        assert!(matches!(result, Err(Error::SrfWrite { .. })));
+    }
+}
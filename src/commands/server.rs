@@ -0,0 +1,265 @@
+use crate::commands::check::{is_pbo, mod_key_files};
+use crate::commands::gen_srf::open_cache_or_gen_srf;
+use crate::commands::launch::repo_mods;
+use crate::md5_digest::Md5Digest;
+use crate::mod_cache;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+// keeps track of the keys nimble put in the server's keys directory, and what they contained, so
+// keys added or changed by hand (or shipped with the game) are never removed
+const MANIFEST_NAME: &str = "nimble-keys.json";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error on {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Failed to open ModCache: {}", source))]
+    ModCacheOpen { source: mod_cache::Error },
+    #[snafu(display("Failed to read key manifest: {}", source))]
+    ManifestDeserialization { source: serde_json::Error },
+    #[snafu(display("Failed to write key manifest: {}", source))]
+    ManifestSerialization { source: serde_json::Error },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    keys: BTreeMap<String, Md5Digest>,
+}
+
+impl Manifest {
+    fn from_disk(out: &Path) -> Result<Self, Error> {
+        let path = out.join(MANIFEST_NAME);
+
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).context(ManifestDeserializationSnafu),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(Error::Io { path, source }),
+        }
+    }
+
+    fn to_disk(&self, out: &Path) -> Result<(), Error> {
+        let path = out.join(MANIFEST_NAME);
+        let bytes = serde_json::to_vec_pretty(self).context(ManifestSerializationSnafu)?;
+
+        std::fs::write(&path, bytes).context(IoSnafu { path })
+    }
+}
+
+fn digest(contents: &[u8]) -> Md5Digest {
+    Md5Digest::from_bytes(Md5::digest(contents).into())
+}
+
+// whether the key in the keys directory is still the one nimble wrote there
+fn is_unchanged(path: &Path, previous: &Manifest, file_name: &str) -> bool {
+    previous.keys.get(file_name).is_some_and(|written| {
+        std::fs::read(path).is_ok_and(|existing| digest(&existing) == *written)
+    })
+}
+
+fn has_pbos(mod_path: &Path) -> bool {
+    WalkDir::new(mod_path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_type().is_file() && is_pbo(entry.path()))
+}
+
+// copies the bikeys of every mod into a dedicated server's keys directory
+pub fn keys(base_path: &Path, out: &Path, repo_name: Option<&str>) -> Result<(), Error> {
+    let mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
+    // disabled mods can still be loaded by players, so their keys are needed too
+    let mut mods = repo_mods(&mod_cache, repo_name);
+    // players may load the optional ones, so the server has to accept them
+    if let Some(repo_name) = repo_name {
+        mods.extend(mod_cache.optional_mods_for_repo(repo_name));
+    }
+    mods.sort_by(|a, b| a.name.cmp(&b.name));
+    mods.dedup_by(|a, b| a.name == b.name);
+
+    // file name -> where it comes from
+    let mut wanted = BTreeMap::new();
+
+    for r#mod in mods {
+        let mod_path = base_path.join(&r#mod.name);
+        let key_files = mod_key_files(&mod_path);
+
+        if key_files.is_empty() && has_pbos(&mod_path) {
+            println!("{} ships pbos but no key", r#mod.name);
+        }
+
+        for path in key_files {
+            let Some(file_name) = path.file_name() else {
+                continue;
+            };
+
+            let file_name = file_name.to_string_lossy().to_string();
+            if let Some(previous) = wanted.insert(file_name.clone(), path) {
+                println!(
+                    "{file_name} is shipped by more than one mod, ignoring {}",
+                    previous.display()
+                );
+            }
+        }
+    }
+
+    std::fs::create_dir_all(out).context(IoSnafu { path: out })?;
+    let previous = Manifest::from_disk(out)?;
+
+    for stale in previous
+        .keys
+        .keys()
+        .filter(|key| !wanted.contains_key(*key))
+    {
+        let path = out.join(stale);
+
+        if !path.exists() {
+            continue;
+        }
+
+        if !is_unchanged(&path, &previous, stale) {
+            println!("{stale} was changed by hand, leaving it");
+            continue;
+        }
+
+        std::fs::remove_file(&path).context(IoSnafu { path: &path })?;
+        println!("removed {stale}");
+    }
+
+    let mut manifest = Manifest::default();
+
+    for (file_name, source) in &wanted {
+        let dest = out.join(file_name);
+        let contents = std::fs::read(source).context(IoSnafu { path: source })?;
+
+        // a key with the same name that nimble didn't write is someone else's
+        if dest.exists() && !is_unchanged(&dest, &previous, file_name) {
+            if std::fs::read(&dest).is_ok_and(|existing| existing != contents) {
+                println!("{file_name} was added or changed by hand, leaving it");
+            }
+            continue;
+        }
+
+        let checksum = digest(&contents);
+        if !dest.exists() || previous.keys.get(file_name) != Some(&checksum) {
+            std::fs::write(&dest, contents).context(IoSnafu { path: &dest })?;
+            println!("added {file_name}");
+        }
+
+        manifest.keys.insert(file_name.clone(), checksum);
+    }
+
+    manifest.to_disk(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gen_srf::gen_srf;
+
+    #[test]
+    fn keys_test() {
+        let base = tempfile::tempdir().unwrap();
+
+        let ace = base.path().join("@ace");
+        std::fs::create_dir_all(ace.join("keys")).unwrap();
+        std::fs::write(ace.join("keys").join("ace_3.15.bikey"), "ace key").unwrap();
+
+        let unsigned = base.path().join("@unsigned").join("addons");
+        std::fs::create_dir_all(&unsigned).unwrap();
+        std::fs::write(unsigned.join("unsigned.pbo"), "not a real pbo").unwrap();
+
+        gen_srf(base.path());
+
+        let out = tempfile::tempdir().unwrap();
+        std::fs::write(out.path().join("a3.bikey"), "vanilla key").unwrap();
+
+        keys(base.path(), out.path(), None).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("ace_3.15.bikey")).unwrap(),
+            "ace key"
+        );
+
+        // a new version of the mod ships a new key
+        std::fs::remove_file(ace.join("keys").join("ace_3.15.bikey")).unwrap();
+        std::fs::write(ace.join("keys").join("ace_3.16.bikey"), "new ace key").unwrap();
+        keys(base.path(), out.path(), None).unwrap();
+
+        assert!(!out.path().join("ace_3.15.bikey").exists());
+        assert!(out.path().join("ace_3.16.bikey").exists());
+        assert!(out.path().join("a3.bikey").exists());
+
+        // keys someone placed or changed by hand are left alone, even if a mod ships one with
+        // the same name
+        let cba = base.path().join("@cba");
+        std::fs::create_dir_all(cba.join("keys")).unwrap();
+        std::fs::write(cba.join("keys").join("cba_3.bikey"), "cba key").unwrap();
+        std::fs::write(out.path().join("cba_3.bikey"), "hand placed cba key").unwrap();
+        std::fs::write(out.path().join("ace_3.16.bikey"), "hand edited ace key").unwrap();
+        gen_srf(base.path());
+
+        keys(base.path(), out.path(), None).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("cba_3.bikey")).unwrap(),
+            "hand placed cba key"
+        );
+
+        std::fs::remove_file(cba.join("keys").join("cba_3.bikey")).unwrap();
+        std::fs::remove_file(ace.join("keys").join("ace_3.16.bikey")).unwrap();
+        keys(base.path(), out.path(), None).unwrap();
+
+        assert!(out.path().join("cba_3.bikey").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("ace_3.16.bikey")).unwrap(),
+            "hand edited ace key"
+        );
+
+        // keys nimble wrote that were deleted by hand are put back
+        std::fs::write(ace.join("keys").join("ace_3.17.bikey"), "newer ace key").unwrap();
+        keys(base.path(), out.path(), None).unwrap();
+        std::fs::remove_file(out.path().join("ace_3.17.bikey")).unwrap();
+        keys(base.path(), out.path(), None).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(out.path().join("ace_3.17.bikey")).unwrap(),
+            "newer ace key"
+        );
+    }
+
+    #[test]
+    fn optional_keys_test() {
+        let base = tempfile::tempdir().unwrap();
+
+        for (name, key) in [
+            ("@ace", "ace.bikey"),
+            ("@jsrs", "jsrs.bikey"),
+            ("@other", "other.bikey"),
+        ] {
+            let keys = base.path().join(name).join("keys");
+            std::fs::create_dir_all(&keys).unwrap();
+            std::fs::write(keys.join(key), name).unwrap();
+        }
+
+        gen_srf(base.path());
+
+        let mut mod_cache = mod_cache::ModCache::from_disk(base.path()).unwrap();
+        mod_cache.update_repo_references("alpha", &["@ace"]);
+        mod_cache
+            .optional_mods
+            .insert("alpha".to_string(), vec!["@JSRS".to_string()]);
+        mod_cache.to_disk(base.path()).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        keys(base.path(), out.path(), Some("alpha")).unwrap();
+
+        assert!(out.path().join("ace.bikey").exists());
+        assert!(out.path().join("jsrs.bikey").exists());
+        assert!(!out.path().join("other.bikey").exists());
+    }
+}
@@ -1,17 +1,23 @@
 use crate::commands::gen_srf::{gen_srf_for_mod, open_cache_or_gen_srf};
+use crate::file_index::FileIndex;
 use crate::mod_cache::ModCache;
-use crate::{repository, srf};
+use crate::source::{self, Source};
+use crate::{mod_cache, pbo, repository, srf};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use snafu::{ResultExt, Snafu};
-use std::collections::HashMap;
+use snafu::{ensure, ResultExt, Snafu};
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom};
-use std::path::Path;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use tempfile::tempfile;
 
 #[derive(Debug)]
 struct DownloadCommand {
     file: String,
+    checksum: String,
+    length: u64,
+    // how the remote srf scanned the file, which decides how the download is verified
+    r#type: srf::FileType,
 
     // These are currently unused. TODO: implement file diffing.
     #[allow(dead_code)]
@@ -25,12 +31,7 @@ pub enum Error {
     #[snafu(display("io error: {}", source))]
     Io { source: std::io::Error },
     #[snafu(display("Error while requesting repository data: {}", source))]
-    Http {
-        url: String,
-
-        #[snafu(source(from(ureq::Error, Box::new)))]
-        source: Box<ureq::Error>,
-    },
+    Source { source: source::Error },
     #[snafu(display("Failed to fetch repository info: {}", source))]
     RepositoryFetch { source: repository::Error },
     #[snafu(display("SRF deserialization failure: {}", source))]
@@ -41,6 +42,21 @@ pub enum Error {
     SrfGeneration { source: srf::Error },
     #[snafu(display("Failed to open ModCache: {}", source))]
     ModCacheOpen { source: crate::mod_cache::Error },
+    #[snafu(display("Downloaded {} is corrupted: {}", file, source))]
+    CorruptPbo { file: String, source: pbo::Error },
+    #[snafu(display(
+        "Downloaded {} is corrupted: expected checksum {}, got {}",
+        file,
+        expected,
+        actual
+    ))]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    #[snafu(display("Failed to write ModCache: {}", source))]
+    ModCacheWrite { source: crate::mod_cache::Error },
 }
 
 fn diff_repo<'a>(
@@ -61,21 +77,18 @@ fn diff_repo<'a>(
     downloads
 }
 
+// returns what to download, and the local files the remote mod doesn't have anymore. those are
+// only removed after the downloads, so they can still be reused
 fn diff_mod(
-    agent: &ureq::Agent,
-    repo_base_path: &str,
+    source: &Source,
     local_base_path: &Path,
     remote_mod: &repository::Mod,
-) -> Result<Vec<DownloadCommand>, Error> {
+) -> Result<(Vec<DownloadCommand>, Vec<PathBuf>), Error> {
     // HACK HACK: this REALLY should be parsed through streaming rather than through buffering the whole thing
-    let remote_srf_url = format!("{}{}/mod.srf", repo_base_path, remote_mod.mod_name);
-    let mut remote_srf = agent
-        .get(&remote_srf_url)
-        .call()
-        .context(HttpSnafu {
-            url: remote_srf_url,
-        })?
-        .into_reader();
+    let mut remote_srf = source
+        .open(&format!("{}/mod.srf", remote_mod.mod_name))
+        .context(SourceSnafu)?
+        .reader;
 
     let mut buf = String::new();
     let _len = remote_srf.read_to_string(&mut buf).context(IoSnafu)?;
@@ -121,7 +134,7 @@ fn diff_mod(
     };
 
     if local_srf.checksum == remote_srf.checksum {
-        return Ok(vec![]);
+        return Ok((vec![], vec![]));
     }
 
     let mut local_files = HashMap::new();
@@ -147,6 +160,9 @@ fn diff_mod(
 
                 download_list.push(DownloadCommand {
                     file: format!("{}/{}", remote_srf.name, path),
+                    checksum: file.checksum.clone(),
+                    length: file.length,
+                    r#type: file.r#type.clone(),
                     begin: 0,
                     end: file.length,
                 });
@@ -154,44 +170,116 @@ fn diff_mod(
         } else {
             download_list.push(DownloadCommand {
                 file: format!("{}/{}", remote_srf.name, path),
+                checksum: file.checksum.clone(),
+                length: file.length,
+                r#type: file.r#type.clone(),
                 begin: 0,
                 end: file.length,
             });
         }
     }
 
-    // remove any local files that remain here
-    remove_leftover_files(local_base_path, &remote_srf, local_files.into_values())
-        .context(IoSnafu)?;
+    let mod_path = local_base_path.join(Path::new(&remote_srf.name));
+
+    // files that only differ in case from one we download go right away, on case insensitive
+    // file systems removing them later would remove the download
+    let (replaced, leftovers): (Vec<_>, Vec<_>) = local_files
+        .into_values()
+        .map(|file| file.path.to_path(&mod_path))
+        .partition(|path| {
+            remote_srf.files.iter().any(|remote| {
+                remote
+                    .path
+                    .to_path(&mod_path)
+                    .as_os_str()
+                    .eq_ignore_ascii_case(path)
+            })
+        });
+
+    remove_leftover_files(&replaced).context(IoSnafu)?;
+
+    Ok((download_list, leftovers))
+}
+
+// remove mods that no repository references anymore
+fn prune_mods(base_path: &Path, mods: &[mod_cache::Mod]) -> Result<(), std::io::Error> {
+    for r#mod in mods {
+        let path = base_path.join(Path::new(&r#mod.name));
+
+        println!("removing unreferenced mod {}", &path.display());
 
-    Ok(download_list)
+        match std::fs::remove_dir_all(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
 }
 
 // remove files that are present in the local disk but not in the remote repo
-fn remove_leftover_files<'a>(
-    local_base_path: &Path,
-    r#mod: &srf::Mod,
-    files: impl Iterator<Item = &'a srf::File>,
-) -> Result<(), std::io::Error> {
-    for file in files {
-        let path = file
-            .path
-            .to_path(local_base_path.join(Path::new(&r#mod.name)));
+fn remove_leftover_files(paths: &[PathBuf]) -> Result<(), std::io::Error> {
+    for path in paths {
+        println!("removing leftover file {}", path.display());
 
-        println!("removing leftover file {}", &path.display());
+        remove_existing_file(path)?;
+    }
+
+    Ok(())
+}
 
-        std::fs::remove_file(&path)?;
+// replaces destination with an identical file found elsewhere in the mod directory
+fn reuse_local_file(source: &Path, destination: &Path, hard_link: bool) -> Result<(), Error> {
+    std::fs::create_dir_all(
+        destination
+            .parent()
+            .expect("destination did not have a parent"),
+    )
+    .context(IoSnafu)?;
+    remove_existing_file(destination).context(IoSnafu)?;
+
+    if hard_link && std::fs::hard_link(source, destination).is_ok() {
+        return Ok(());
     }
 
+    std::fs::copy(source, destination).context(IoSnafu)?;
+
     Ok(())
 }
 
+// files may be hard linked into other mods, so never truncate them in place
+fn remove_existing_file(path: &Path) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
 fn execute_command_list(
-    agent: &mut ureq::Agent,
-    remote_base: &str,
+    source: &Source,
     local_base: &Path,
     commands: &[DownloadCommand],
+    index: &FileIndex,
+    hard_link: bool,
 ) -> Result<(), Error> {
+    // reuse local copies before downloading anything, downloads may overwrite the files we'd copy from
+    let mut downloads = Vec::new();
+
+    for command in commands {
+        let destination = local_base.join(Path::new(&command.file));
+
+        match index.find(&command.checksum, command.length) {
+            Some(source) if source != destination => {
+                println!("reusing {} for {}", source.display(), command.file);
+                reuse_local_file(source, &destination, hard_link)?;
+            }
+            _ => downloads.push(command),
+        }
+    }
+
+    let commands = downloads;
+
     for (i, command) in commands.iter().enumerate() {
         println!("downloading {} of {} - {}", i, commands.len(), command.file);
 
@@ -199,15 +287,10 @@ fn execute_command_list(
         // which will later make us crash in gen_srf
         let mut temp_download_file = tempfile().context(IoSnafu)?;
 
-        let remote_url = format!("{}{}", remote_base, command.file);
-
-        let response = agent.get(&remote_url).call().context(HttpSnafu {
-            url: remote_url.clone(),
-        })?;
+        let download = source.open(&command.file).context(SourceSnafu)?;
 
-        let pb = response
-            .header("Content-Length")
-            .and_then(|len| len.parse().ok())
+        let pb = download
+            .length
             .map_or_else(ProgressBar::new_spinner, ProgressBar::new);
 
         pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
@@ -215,14 +298,51 @@ fn execute_command_list(
             .with_key("eta", |state: &ProgressState, w: &mut dyn std::fmt::Write| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
             .progress_chars("#>-"));
 
-        let reader = response.into_reader();
+        std::io::copy(&mut pb.wrap_read(download.reader), &mut temp_download_file)
+            .context(IoSnafu)?;
 
-        std::io::copy(&mut pb.wrap_read(reader), &mut temp_download_file).context(IoSnafu)?;
+        // catch broken uploads and truncated downloads before they replace a working file
+        temp_download_file
+            .seek(SeekFrom::Start(0))
+            .context(IoSnafu)?;
+
+        match command.r#type {
+            srf::FileType::Pbo => {
+                match pbo::verify_file(BufReader::new(&mut temp_download_file)) {
+                    Ok(()) => {}
+                    // older pbos were never checksummed
+                    Err(pbo::Error::MissingChecksum) => {
+                        println!("{} has no checksum, skipping verification", command.file);
+                    }
+                    Err(e) => {
+                        return Err(Error::CorruptPbo {
+                            file: command.file.clone(),
+                            source: e,
+                        })
+                    }
+                }
+            }
+            // including .pbo files that aren't valid pbos, which are synced as they are
+            srf::FileType::File => {
+                let actual = srf::file_checksum(&mut BufReader::new(&mut temp_download_file))
+                    .context(IoSnafu)?;
+
+                ensure!(
+                    actual.eq_ignore_ascii_case(&command.checksum),
+                    ChecksumMismatchSnafu {
+                        file: &command.file,
+                        expected: &command.checksum,
+                        actual
+                    }
+                );
+            }
+        }
 
         // copy from temp to permanent file
         let file_path = local_base.join(Path::new(&command.file));
         std::fs::create_dir_all(file_path.parent().expect("file_path did not have a parent"))
             .context(IoSnafu)?;
+        remove_existing_file(&file_path).context(IoSnafu)?;
         let mut local_file = File::create(&file_path).context(IoSnafu)?;
 
         temp_download_file
@@ -235,38 +355,92 @@ fn execute_command_list(
 }
 
 pub fn sync(
-    agent: &mut ureq::Agent,
-    repo_url: &str,
+    source: &Source,
     base_path: &Path,
     dry_run: bool,
+    hard_link: bool,
 ) -> Result<(), Error> {
-    let remote_repo = repository::get_repository_info(agent, &format!("{repo_url}/repo.json"))
-        .context(RepositoryFetchSnafu)?;
+    let remote_repo = repository::get_repository_info(source).context(RepositoryFetchSnafu)?;
 
     let mut mod_cache = open_cache_or_gen_srf(base_path).context(ModCacheOpenSnafu)?;
 
     let check = diff_repo(&mod_cache, &remote_repo);
 
+    // index the files we already have before diffing, as diffing may remove some of them
+    let index = FileIndex::build(base_path);
+
     println!("mods to check: {check:#?}");
 
-    // remove all mods to check from cache, we'll read them later
+    // remove all mods to check from cache, we'll read them later. other repositories may share
+    // these mods, so remember who referenced them
+    let mut previous_repos = HashMap::new();
+
     for r#mod in &check {
-        mod_cache.remove(&r#mod.checksum);
+        let repos: BTreeSet<String> = mod_cache
+            .remove_by_name(&r#mod.mod_name)
+            .into_iter()
+            .flat_map(|old| old.repos)
+            .collect();
+
+        let others: Vec<_> = repos
+            .iter()
+            .filter(|repo| **repo != remote_repo.repo_name)
+            .collect();
+
+        if !others.is_empty() {
+            println!(
+                "{} is shared with {others:?}, it will be updated to the version required by {}",
+                r#mod.mod_name, remote_repo.repo_name
+            );
+        }
+
+        previous_repos.insert(r#mod.mod_name.as_str(), repos);
     }
 
+    let required_names: Vec<_> = remote_repo
+        .required_mods
+        .iter()
+        .map(|r#mod| r#mod.mod_name.as_str())
+        .collect();
+
+    mod_cache.optional_mods.insert(
+        remote_repo.repo_name.clone(),
+        remote_repo
+            .optional_mods
+            .iter()
+            .map(|r#mod| r#mod.mod_name.clone())
+            .collect(),
+    );
+
+    let orphans: Vec<_> = mod_cache
+        .update_repo_references(&remote_repo.repo_name, &required_names)
+        .iter()
+        .filter_map(|checksum| mod_cache.remove(checksum))
+        .collect();
+
+    println!(
+        "mods no longer required by any repository: {:#?}",
+        orphans.iter().map(|r#mod| &r#mod.name).collect::<Vec<_>>()
+    );
+
     let mut download_commands = vec![];
+    let mut leftovers = vec![];
 
     for r#mod in &check {
-        download_commands.extend(diff_mod(agent, repo_url, base_path, r#mod).unwrap());
+        let (commands, mod_leftovers) = diff_mod(source, base_path, r#mod).unwrap();
+        download_commands.extend(commands);
+        leftovers.extend(mod_leftovers);
     }
 
     println!("download commands: {download_commands:#?}");
+    println!("leftover files: {leftovers:#?}");
 
     if dry_run {
         return Ok(());
     }
 
-    let res = execute_command_list(agent, repo_url, base_path, &download_commands);
+    let res = execute_command_list(source, base_path, &download_commands, &index, hard_link)
+        .and_then(|()| remove_leftover_files(&leftovers).context(IoSnafu));
 
     if let Err(e) = res {
         println!("an error occured while downloading: {e}");
@@ -277,12 +451,152 @@ pub fn sync(
     for r#mod in &check {
         let srf = gen_srf_for_mod(&base_path.join(Path::new(&r#mod.mod_name)));
 
-        mod_cache.insert(srf);
+        let cached = mod_cache.insert(srf);
+        cached.repos.extend(
+            previous_repos
+                .remove(r#mod.mod_name.as_str())
+                .unwrap_or_default(),
+        );
+        cached.repos.insert(remote_repo.repo_name.clone());
     }
 
+    // the repository decides whether its mods are loaded by default
+    for remote_mod in &remote_repo.required_mods {
+        for cached in mod_cache
+            .mods
+            .values_mut()
+            .filter(|cached| cached.name.eq_ignore_ascii_case(&remote_mod.mod_name))
+        {
+            cached.enabled = remote_mod.enabled;
+        }
+    }
+
+    prune_mods(base_path, &orphans).context(IoSnafu)?;
+
     // reserialize the cache
-    let writer = BufWriter::new(File::create(base_path.join("nimble-cache.json")).unwrap());
-    serde_json::to_writer(writer, &mod_cache).unwrap();
+    mod_cache.to_disk(base_path).context(ModCacheWriteSnafu)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_repo(remote: &Path, srfs: &[srf::Mod]) {
+        let repository = repository::Repository {
+            repo_name: "remote".to_string(),
+            checksum: String::new(),
+            required_mods: srfs
+                .iter()
+                .map(|srf| repository::Mod {
+                    mod_name: srf.name.clone(),
+                    checksum: srf.checksum.clone(),
+                    enabled: true,
+                })
+                .collect(),
+            optional_mods: vec![],
+            client_parameters: String::new(),
+            repo_basic_authentication: None,
+            version: "1".to_string(),
+            servers: vec![],
+        };
+
+        std::fs::write(
+            remote.join("repo.json"),
+            serde_json::to_vec(&repository).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_pbos_are_synced_as_files() {
+        let remote = tempfile::tempdir().unwrap();
+        let addons = remote.path().join("@mod").join("addons");
+        std::fs::create_dir_all(&addons).unwrap();
+        std::fs::write(addons.join("broken.pbo"), "not a real pbo").unwrap();
+        let srf = gen_srf_for_mod(&remote.path().join("@mod"));
+        write_repo(remote.path(), &[srf]);
+
+        let local = tempfile::tempdir().unwrap();
+        let source = Source::new(
+            ureq::AgentBuilder::new().build(),
+            &remote.path().to_string_lossy(),
+        )
+        .unwrap();
+        sync(&source, local.path(), false, false).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(local.path().join("@mod/addons/broken.pbo")).unwrap(),
+            "not a real pbo"
+        );
+
+        // files that don't match the srf are refused
+        std::fs::write(addons.join("broken.pbo"), "not a real pbo either").unwrap();
+        let command = DownloadCommand {
+            file: "@mod/addons/broken.pbo".to_string(),
+            checksum: srf::file_checksum(&mut "not a real pbo".as_bytes()).unwrap(),
+            length: 14,
+            r#type: srf::FileType::File,
+            begin: 0,
+            end: 14,
+        };
+
+        let result = execute_command_list(
+            &source,
+            local.path(),
+            &[command],
+            &FileIndex::default(),
+            false,
+        );
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn renamed_files_are_reused() {
+        let remote = tempfile::tempdir().unwrap();
+        let remote_mod = remote.path().join("@mod");
+        std::fs::create_dir_all(&remote_mod).unwrap();
+        std::fs::write(remote_mod.join("new.txt"), "hello").unwrap();
+        let remote_srf = gen_srf_for_mod(&remote_mod);
+
+        let local = tempfile::tempdir().unwrap();
+        let local_mod = local.path().join("@mod");
+        std::fs::create_dir_all(&local_mod).unwrap();
+        std::fs::write(local_mod.join("old.txt"), "hello").unwrap();
+        gen_srf_for_mod(&local_mod);
+
+        let source = Source::new(
+            ureq::AgentBuilder::new().build(),
+            &remote.path().to_string_lossy(),
+        )
+        .unwrap();
+        let (commands, leftovers) = diff_mod(
+            &source,
+            local.path(),
+            &repository::Mod {
+                mod_name: "@mod".to_string(),
+                checksum: remote_srf.checksum,
+                enabled: true,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        assert_eq!(leftovers, [local_mod.join("old.txt")]);
+        assert!(local_mod.join("old.txt").exists());
+
+        // so it can't be downloaded instead
+        std::fs::remove_file(remote_mod.join("new.txt")).unwrap();
+
+        let index = FileIndex::build(local.path());
+        execute_command_list(&source, local.path(), &commands, &index, false).unwrap();
+        remove_leftover_files(&leftovers).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(local_mod.join("new.txt")).unwrap(),
+            "hello"
+        );
+        assert!(!local_mod.join("old.txt").exists());
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// lives next to nimble-cache.json. it's meant to be edited by hand, nimble only adds profiles
+const CONFIG_NAME: &str = "nimble-config.json";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("failed to read {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("failed to write {}: {}", path.display(), source))]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("failed to parse {}: {}", path.display(), source))]
+    Deserialization {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display("serde failed to serialize: {}", source))]
+    Serialization { source: serde_json::Error },
+}
+
+// nimble writes profiles back into the file, so anything it doesn't know about would be lost.
+// refuse those fields instead, which also catches typos
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub load_order: LoadOrder,
+    // named mod lists launch can load instead of a repository's mods
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    pub fn from_disk_or_default(base_path: &Path) -> Result<Self, Error> {
+        let path = base_path.join(CONFIG_NAME);
+
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).context(DeserializationSnafu { path }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(Error::Io { path, source }),
+        }
+    }
+
+    pub fn to_disk(&self, base_path: &Path) -> Result<(), Error> {
+        let path = base_path.join(CONFIG_NAME);
+        let bytes = serde_json::to_vec_pretty(self).context(SerializationSnafu)?;
+
+        std::fs::write(&path, bytes).context(WriteSnafu { path })
+    }
+}
+
+// rules on top of the order the repositories declare. mod names are case insensitive, and names
+// of mods that aren't loaded are ignored
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LoadOrder {
+    // loaded before everything else, in this order
+    #[serde(default)]
+    pub first: Vec<String>,
+    // loaded after first, in this order, before the mods that aren't mentioned anywhere
+    #[serde(default)]
+    pub order: Vec<String>,
+    // loaded after everything else, in this order
+    #[serde(default)]
+    pub last: Vec<String>,
+}
+
+impl LoadOrder {
+    // reorders items, which should already be in their default order
+    pub fn apply<T>(&self, mut items: Vec<T>, name: impl Fn(&T) -> &str) -> Vec<T> {
+        let mut take = |wanted: &str| {
+            items
+                .iter()
+                .position(|item| name(item).eq_ignore_ascii_case(wanted))
+                .map(|index| items.remove(index))
+        };
+
+        let first: Vec<T> = self.first.iter().filter_map(|n| take(n)).collect();
+        let order: Vec<T> = self.order.iter().filter_map(|n| take(n)).collect();
+        let last: Vec<T> = self.last.iter().filter_map(|n| take(n)).collect();
+
+        first
+            .into_iter()
+            .chain(order)
+            .chain(items)
+            .chain(last)
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    // folder names of the mods in storage, in their default load order
+    pub mods: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_order_test() {
+        let load_order: LoadOrder = serde_json::from_str(
+            r#"{ "first": ["@CBA_A3"], "order": ["@rhs", "@missing"], "last": ["@compat"] }"#,
+        )
+        .unwrap();
+
+        let names = vec!["@ace", "@compat", "@cba_a3", "@tfar", "@rhs"];
+
+        assert_eq!(
+            load_order.apply(names, |name| name),
+            ["@cba_a3", "@rhs", "@ace", "@tfar", "@compat"]
+        );
+    }
+
+    #[test]
+    fn missing_config() {
+        let base = tempfile::tempdir().unwrap();
+
+        let config = Config::from_disk_or_default(base.path()).unwrap();
+        assert_eq!(config.load_order, LoadOrder::default());
+
+        std::fs::write(base.path().join(CONFIG_NAME), "{}").unwrap();
+        let config = Config::from_disk_or_default(base.path()).unwrap();
+        assert_eq!(config.load_order, LoadOrder::default());
+        assert!(config.profiles.is_empty());
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let base = tempfile::tempdir().unwrap();
+
+        std::fs::write(
+            base.path().join(CONFIG_NAME),
+            r#"{ "load_order": { "frist": ["@CBA_A3"] } }"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            Config::from_disk_or_default(base.path()),
+            Err(Error::Deserialization { .. })
+        ));
+    }
+}
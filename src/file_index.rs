@@ -0,0 +1,182 @@
+use crate::srf;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Debug)]
+struct IndexedFile {
+    path: PathBuf,
+    length: u64,
+    r#type: srf::FileType,
+}
+
+// maps file checksums to every local copy of that file. swifty file checksums are derived from
+// the part checksums, so two files with the same checksum and length have identical contents
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    files: HashMap<String, Vec<IndexedFile>>,
+}
+
+impl FileIndex {
+    // index every mod in base_path that has a mod.srf, in either format. mods without one are
+    // skipped, we just won't reuse files from them
+    pub fn build(base_path: &Path) -> Self {
+        let mut index = Self::default();
+
+        let mod_paths = WalkDir::new(base_path)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_dir() && e.file_name().to_string_lossy().starts_with('@'))
+            .map(|e| e.path().to_owned());
+
+        for mod_path in mod_paths {
+            let Ok(file) = File::open(mod_path.join("mod.srf")) else {
+                continue;
+            };
+
+            let mut reader = BufReader::new(file);
+            let r#mod = match srf::is_legacy_srf(&mut reader) {
+                Ok(true) => srf::deserialize_legacy_srf(&mut reader).ok(),
+                Ok(false) => serde_json::from_reader::<_, srf::Mod>(reader).ok(),
+                Err(_) => None,
+            };
+
+            if let Some(r#mod) = r#mod {
+                index.insert(&mod_path, &r#mod);
+            }
+        }
+
+        index
+    }
+
+    pub fn insert(&mut self, mod_path: &Path, r#mod: &srf::Mod) {
+        for file in &r#mod.files {
+            self.files
+                .entry(file.checksum.clone())
+                .or_default()
+                .push(IndexedFile {
+                    path: file.path.to_path(mod_path),
+                    length: file.length,
+                    r#type: file.r#type.clone(),
+                });
+        }
+    }
+
+    // srf files may be stale, and files may have been changed by hand or overwritten since, so
+    // copies are hashed again before they are returned
+    pub fn find(&self, checksum: &str, length: u64) -> Option<&Path> {
+        self.files.get(checksum)?.iter().find_map(|file| {
+            let on_disk = std::fs::metadata(&file.path).ok()?;
+
+            if file.length != length || !on_disk.is_file() || on_disk.len() != length {
+                return None;
+            }
+
+            srf::rescan_checksum(&file.path, &file.r#type)
+                .is_ok_and(|actual| actual.eq_ignore_ascii_case(checksum))
+                .then_some(file.path.as_path())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn finds_identical_file_in_other_mod() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let ace_path: PathBuf = [project_root, "test_files", "@ace"].iter().collect();
+        let ace = srf::scan_mod(&ace_path).unwrap();
+
+        let mut index = FileIndex::default();
+        index.insert(&ace_path, &ace);
+
+        let pbo = ace
+            .files
+            .iter()
+            .find(|file| file.path.as_str().ends_with(".pbo"))
+            .unwrap();
+
+        assert_eq!(
+            index.find(&pbo.checksum, pbo.length),
+            Some(pbo.path.to_path(&ace_path).as_path())
+        );
+        assert_eq!(index.find(&pbo.checksum, pbo.length + 1), None);
+        assert_eq!(index.find("not a checksum", pbo.length), None);
+    }
+
+    #[test]
+    fn indexes_legacy_srfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mod_path = dir.path().join("@legacy");
+        std::fs::create_dir_all(&mod_path).unwrap();
+        std::fs::write(mod_path.join("readme.txt"), b"hello").unwrap();
+
+        let file = srf::scan_file(&mod_path.join("readme.txt"), &mod_path).unwrap();
+        let part = &file.parts[0];
+        std::fs::write(
+            mod_path.join("mod.srf"),
+            format!(
+                "ADDON:@legacy:1:00000000000000000000000000000000\n\
+                 FILE:readme.txt:5:1:{}\n\
+                 {}:0:5:{}\n",
+                file.checksum, part.path, part.checksum
+            ),
+        )
+        .unwrap();
+
+        let index = FileIndex::build(dir.path());
+        assert_eq!(
+            index.find(&file.checksum, file.length),
+            Some(mod_path.join("readme.txt").as_path())
+        );
+    }
+
+    #[test]
+    fn skips_files_that_changed_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mod_path = dir.path().join("@fork");
+        std::fs::create_dir_all(&mod_path).unwrap();
+        std::fs::write(mod_path.join("readme.txt"), b"hello").unwrap();
+
+        let r#mod = srf::scan_mod(&mod_path).unwrap();
+        let file = &r#mod.files[0];
+
+        let mut index = FileIndex::default();
+        index.insert(&mod_path, &r#mod);
+        assert!(index.find(&file.checksum, file.length).is_some());
+
+        File::options()
+            .append(true)
+            .open(mod_path.join("readme.txt"))
+            .unwrap()
+            .write_all(b", world")
+            .unwrap();
+
+        assert!(index.find(&file.checksum, file.length).is_none());
+    }
+
+    #[test]
+    fn skips_files_with_changed_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mod_path = dir.path().join("@fork");
+        std::fs::create_dir_all(&mod_path).unwrap();
+        std::fs::write(mod_path.join("readme.txt"), b"hello").unwrap();
+
+        let r#mod = srf::scan_mod(&mod_path).unwrap();
+        let file = &r#mod.files[0];
+
+        let mut index = FileIndex::default();
+        index.insert(&mod_path, &r#mod);
+
+        // same length, different contents
+        std::fs::write(mod_path.join("readme.txt"), b"world").unwrap();
+        assert!(index.find(&file.checksum, file.length).is_none());
+    }
+}
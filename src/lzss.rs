@@ -0,0 +1,137 @@
+use snafu::{ensure, ResultExt, Snafu};
+use std::io::Read;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("back reference with a distance of zero at {}", position))]
+    InvalidReference { position: usize },
+    #[snafu(display("checksum mismatch: expected {:#x}, got {:#x}", expected, actual))]
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+fn read_u8<I: Read>(input: &mut I) -> Result<u8, Error> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf).context(IoSnafu)?;
+
+    Ok(buf[0])
+}
+
+// decompresses BI's flavour of LZSS, as used by Cprs pbo entries. every flag bit (lsb first) says
+// whether the next item is a literal byte or a 12 bit distance + 4 bit length back reference.
+// the stream is followed by an additive checksum of the decompressed bytes
+pub fn decompress<I: Read>(input: &mut I, expected_len: usize) -> Result<Vec<u8>, Error> {
+    // the expected length comes straight from the pbo header, don't trust it for the allocation
+    let mut output = Vec::with_capacity(expected_len.min(1 << 24));
+
+    while output.len() < expected_len {
+        let flags = read_u8(input)?;
+
+        for bit in 0..8 {
+            if output.len() >= expected_len {
+                break;
+            }
+
+            if flags & (1 << bit) != 0 {
+                output.push(read_u8(input)?);
+                continue;
+            }
+
+            let low = read_u8(input)?;
+            let high = read_u8(input)?;
+
+            let distance = usize::from(low) | (usize::from(high & 0xF0) << 4);
+            let length = (usize::from(high & 0x0F) + 3).min(expected_len - output.len());
+
+            ensure!(
+                distance != 0,
+                InvalidReferenceSnafu {
+                    position: output.len()
+                }
+            );
+
+            // references before the start of the output are filled with spaces
+            let start = output.len() as isize - distance as isize;
+
+            for position in start..start + length as isize {
+                let byte = usize::try_from(position).map_or(b' ', |position| output[position]);
+                output.push(byte);
+            }
+        }
+    }
+
+    let mut checksum = [0; 4];
+    input.read_exact(&mut checksum).context(IoSnafu)?;
+    let expected = u32::from_le_bytes(checksum);
+
+    let actual = output
+        .iter()
+        .fold(0u32, |acc, byte| acc.wrapping_add(u32::from(*byte)));
+
+    ensure!(
+        expected == actual,
+        ChecksumMismatchSnafu { expected, actual }
+    );
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_and_back_references() {
+        // "abc" as literals, then a reference 3 bytes back with a length of 6
+        let compressed = [0x07, b'a', b'b', b'c', 0x03, 0x03, 0x72, 0x03, 0x00, 0x00];
+        let output = decompress(&mut compressed.as_slice(), 9).unwrap();
+
+        assert_eq!(output, b"abcabcabc");
+    }
+
+    #[test]
+    fn references_before_start_are_spaces() {
+        // "x", then a reference 2 bytes back with a length of 3
+        let compressed = [0x01, b'x', 0x02, 0x00, 0x30, 0x01, 0x00, 0x00];
+        let output = decompress(&mut compressed.as_slice(), 4).unwrap();
+
+        assert_eq!(output, b"x x ");
+    }
+
+    #[test]
+    fn compressed_pbo_entries() {
+        use crate::pbo::Pbo;
+        use std::io::Cursor;
+        use std::path::PathBuf;
+
+        // packed by test_files/lzss/compress.py from the files next to it
+        let lzss_path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test_files", "lzss"]
+            .iter()
+            .collect();
+        let bytes = std::fs::read(lzss_path.join("compressed.pbo")).unwrap();
+        let mut pbo = Pbo::read(Cursor::new(bytes)).unwrap();
+        pbo.verify().unwrap();
+
+        for name in ["config.cpp", "functions/fnc_handleFired.sqf"] {
+            let index = pbo
+                .entries
+                .iter()
+                .position(|entry| entry.filename.replace('\\', "/") == name)
+                .unwrap();
+            assert!(pbo.entries[index].is_compressed());
+            assert!(pbo.entries[index].data_size < pbo.entries[index].original_size);
+
+            let expected = std::fs::read(lzss_path.join("addon").join(name)).unwrap();
+            assert_eq!(pbo.read_entry_at(index).unwrap(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let compressed = [0x07, b'a', b'b', b'c', 0x03, 0x03, 0x00, 0x00, 0x00, 0x00];
+        let result = decompress(&mut compressed.as_slice(), 9);
+
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+    }
+}
@@ -1,33 +1,296 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 
 mod commands;
+mod config;
+mod file_index;
+mod lzss;
 mod md5_digest;
 mod mod_cache;
+mod mod_info;
 mod pbo;
+mod preset;
+mod rap;
 mod repository;
+mod signature;
+mod source;
 mod srf;
+mod steam;
+
+#[derive(Subcommand)]
+enum PboCommands {
+    /// Extract the entries of a PBO into a directory
+    Extract {
+        file: PathBuf,
+        dir: PathBuf,
+
+        /// Only extract these entries
+        entries: Vec<String>,
+    },
+    /// Pack a directory into a PBO
+    Pack {
+        dir: PathBuf,
+        out: PathBuf,
+
+        /// Defaults to the contents of $PBOPREFIX$, if present
+        #[clap(long)]
+        prefix: Option<String>,
+    },
+    /// Print the CfgPatches declared by a PBO's config.bin
+    Config { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum CheckCommands {
+    /// Verify the trailing checksum of every PBO
+    Integrity {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+    },
+    /// Report PBOs that share a prefix or a file name across the mods launch would load
+    Conflicts {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// Only check the mods required by this repository
+        #[clap(short, long)]
+        repo: Option<String>,
+    },
+    /// Verify PBO signatures against the keys the mods ship
+    Signatures {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// Only check the mods required by this repository
+        #[clap(short, long)]
+        repo: Option<String>,
+    },
+    /// Report required addons that none of the mods launch would load provide
+    Deps {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// Only check the mods required by this repository
+        #[clap(short, long)]
+        repo: Option<String>,
+
+        /// Addons to assume are present, such as CDLC ones. A trailing * matches a prefix
+        #[clap(long)]
+        ignore: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModsCommands {
+    /// Show the name, Workshop ID, version, size and enabled state of every mod
+    List {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// Only list the mods required by this repository
+        #[clap(short, long)]
+        repo: Option<String>,
+    },
+    /// Show the mods launch would load, in the order it would load them
+    Order {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// Only load the mods required by this repository
+        #[clap(short, long, conflicts_with = "profile")]
+        repo: Option<String>,
+
+        /// Load the mods of this profile instead
+        #[clap(long)]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PresetCommands {
+    /// Create a launch profile from an Arma 3 Launcher preset
+    Import {
+        /// The preset, as exported by the launcher
+        file: PathBuf,
+
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// Name of the profile. Defaults to the name of the preset
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Write the mods launch would load as an Arma 3 Launcher preset
+    Export {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// Where to write the preset
+        #[clap(short, long)]
+        out: PathBuf,
+
+        /// Only export the mods required by this repository
+        #[clap(short, long, conflicts_with = "profile")]
+        repo: Option<String>,
+
+        /// Export the mods of this profile instead
+        #[clap(long)]
+        profile: Option<String>,
+
+        /// Name of the preset. Defaults to the profile or repository name
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServerCommands {
+    /// Copy the keys of every mod into a dedicated server's keys directory
+    Keys {
+        #[clap(short, long)]
+        path: Option<PathBuf>,
+
+        /// The server's keys directory
+        #[clap(short, long)]
+        out: PathBuf,
+
+        /// Only copy the keys of the mods this repository lists, required or optional
+        #[clap(short, long)]
+        repo: Option<String>,
+    },
+}
 
 #[derive(Subcommand)]
 enum Commands {
     Sync {
+        /// Repository URL. file:// URLs and local paths are also accepted
+        #[clap(short, long)]
+        repo_url: String,
+
+        #[clap(short, long)]
+        path: PathBuf,
+
+        #[clap(short, long)]
+        dry_run: bool,
+
+        /// Hard link files that already exist in other mods instead of copying them
+        #[clap(long)]
+        hard_link: bool,
+    },
+    /// Package a repository and its mods into a single archive
+    Export {
         #[clap(short, long)]
         repo_url: String,
 
         #[clap(short, long)]
         path: PathBuf,
 
+        #[clap(short, long)]
+        out: PathBuf,
+    },
+    /// Apply an archive created by export, as if it was synced from its repository
+    Import {
+        bundle: PathBuf,
+
+        #[clap(short, long)]
+        path: PathBuf,
+
         #[clap(short, long)]
         dry_run: bool,
     },
+    /// Serve a mod storage path as a Swifty repository over HTTP
+    Serve {
+        #[clap(short, long)]
+        path: PathBuf,
+
+        #[clap(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Repository name, defaults to the directory name
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Inspect the mods in a mod storage path
+    Mods {
+        #[clap(subcommand)]
+        command: ModsCommands,
+    },
+    /// Convert between Arma 3 Launcher presets and launch profiles
+    Preset {
+        #[clap(subcommand)]
+        command: PresetCommands,
+    },
+    /// Manage a dedicated server
+    Server {
+        #[clap(subcommand)]
+        command: ServerCommands,
+    },
+    /// Inspect PBO files
+    Pbo {
+        #[clap(subcommand)]
+        command: PboCommands,
+    },
+    /// Look for problems in a mod storage path
+    Check {
+        #[clap(subcommand)]
+        command: CheckCommands,
+    },
     GenSrf {
         #[clap(short, long)]
         path: PathBuf,
     },
     Launch {
         #[clap(short, long)]
-        path: PathBuf,
+        path: Option<PathBuf>,
+
+        /// Only load the mods required by this repository
+        #[clap(short, long, conflicts_with = "profile")]
+        repo: Option<String>,
+
+        /// Load the mods of this profile, e.g. one imported from a preset, instead
+        #[clap(long)]
+        profile: Option<String>,
+
+        /// Refuse to launch if a required addon is missing
+        #[clap(long)]
+        check_deps: bool,
+
+        /// Addons to assume are present when checking dependencies. A trailing * matches a prefix
+        #[clap(long, requires = "check_deps")]
+        ignore: Vec<String>,
+
+        /// How to start the game. direct waits for it to exit
+        #[clap(long, value_enum, default_value_t)]
+        backend: commands::launch::Backend,
+
+        /// Proton script to run the game with when launching directly on Linux
+        #[clap(long)]
+        proton: Option<PathBuf>,
+
+        /// Command to run the game with instead of Proton, e.g. "umu-run". Split on whitespace
+        #[clap(long, conflicts_with = "proton")]
+        wrapper: Option<String>,
+
+        /// Pass the mod list through a -par= parameter file, for mod lists too long for a command line
+        #[clap(long)]
+        par_file: bool,
+
+        /// Print the mods, arguments and the URL or command that would be used, without starting the game
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Which build of the game to launch. Detected from the Steam install by default
+        #[clap(long, value_enum)]
+        platform: Option<commands::launch::Platform>,
+
+        /// Also load this downloaded Steam Workshop item, unless storage has it already. Can be repeated
+        #[clap(long)]
+        workshop: Vec<u64>,
+
+        /// Also load the Steam Workshop items listed in this Arma 3 Launcher preset
+        #[clap(long)]
+        workshop_preset: Option<PathBuf>,
     },
 }
 
@@ -35,28 +298,206 @@ enum Commands {
 struct Args {
     #[clap(subcommand)]
     command: Commands,
+
+    /// Where Steam is installed, used to find Arma 3. Defaults to Steam's usual locations
+    #[clap(long, global = true)]
+    steam_root: Option<PathBuf>,
+}
+
+// mods are stored in the game's directory unless told otherwise
+fn mod_path(path: Option<PathBuf>, steam_root: Option<&Path>) -> Result<PathBuf, steam::Error> {
+    match path {
+        Some(path) => Ok(path),
+        None => steam::find_arma_in(steam_root).map(|arma| arma.install_dir),
+    }
+}
+
+// mod_path, exiting like clap does for a missing argument if there's no game directory to use
+fn mod_path_or_exit(path: Option<PathBuf>, steam_root: Option<&Path>) -> PathBuf {
+    mod_path(path, steam_root).unwrap_or_else(|e| {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                format!("--path was not given and the Arma 3 directory can't be used instead: {e}"),
+            )
+            .exit()
+    })
 }
 
 fn main() {
-    let args = Args::parse();
+    let Args {
+        command,
+        steam_root,
+    } = Args::parse();
 
-    let mut agent = ureq::AgentBuilder::new()
+    let agent = ureq::AgentBuilder::new()
         .user_agent("nimble (like Swifty)/0.1")
         .build();
 
-    match args.command {
+    match command {
         Commands::Sync {
             repo_url,
             path,
             dry_run,
+            hard_link,
+        } => {
+            let source = source::Source::new(agent, &repo_url).unwrap();
+
+            commands::sync::sync(&source, &path, dry_run, hard_link).unwrap();
+        }
+        Commands::Export {
+            repo_url,
+            path,
+            out,
+        } => {
+            let source = source::Source::new(agent, &repo_url).unwrap();
+
+            commands::bundle::export(&source, &path, &out).unwrap();
+        }
+        Commands::Import {
+            bundle,
+            path,
+            dry_run,
         } => {
-            commands::sync::sync(&mut agent, &repo_url, &path, dry_run).unwrap();
+            commands::bundle::import(&bundle, &path, dry_run).unwrap();
+        }
+        Commands::Serve { path, port, name } => {
+            commands::serve::serve(&path, port, name.as_deref()).unwrap();
+        }
+        Commands::Mods { command } => match command {
+            ModsCommands::List { path, repo } => {
+                commands::mods::list(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    repo.as_deref(),
+                )
+                .unwrap();
+            }
+            ModsCommands::Order {
+                path,
+                repo,
+                profile,
+            } => {
+                commands::mods::order(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    repo.as_deref(),
+                    profile.as_deref(),
+                )
+                .unwrap();
+            }
+        },
+        Commands::Preset { command } => match command {
+            PresetCommands::Import { file, path, name } => {
+                commands::preset::import(&path, &file, name.as_deref()).unwrap();
+            }
+            PresetCommands::Export {
+                path,
+                out,
+                repo,
+                profile,
+                name,
+            } => {
+                commands::preset::export(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    &out,
+                    repo.as_deref(),
+                    profile.as_deref(),
+                    name.as_deref(),
+                )
+                .unwrap();
+            }
+        },
+        Commands::Server { command } => match command {
+            ServerCommands::Keys { path, out, repo } => {
+                commands::server::keys(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    &out,
+                    repo.as_deref(),
+                )
+                .unwrap();
+            }
+        },
+        Commands::Pbo { command } => match command {
+            PboCommands::Extract { file, dir, entries } => {
+                commands::pbo::extract(&file, &dir, &entries).unwrap();
+            }
+            PboCommands::Pack { dir, out, prefix } => {
+                commands::pbo::pack(&dir, &out, prefix.as_deref()).unwrap();
+            }
+            PboCommands::Config { file } => {
+                commands::pbo::config(&file).unwrap();
+            }
+        },
+        Commands::Check { command } => {
+            let problems = match command {
+                CheckCommands::Integrity { path } => {
+                    commands::check::integrity(&mod_path_or_exit(path, steam_root.as_deref()))
+                        .unwrap()
+                }
+                CheckCommands::Conflicts { path, repo } => commands::check::conflicts(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    repo.as_deref(),
+                )
+                .unwrap(),
+                CheckCommands::Signatures { path, repo } => commands::check::signatures(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    repo.as_deref(),
+                )
+                .unwrap(),
+                CheckCommands::Deps { path, repo, ignore } => commands::check::deps(
+                    &mod_path_or_exit(path, steam_root.as_deref()),
+                    repo.as_deref(),
+                    &ignore,
+                )
+                .unwrap(),
+            };
+
+            if problems > 0 {
+                std::process::exit(1);
+            }
         }
         Commands::GenSrf { path } => {
             commands::gen_srf::gen_srf(&path);
         }
-        Commands::Launch { path } => {
-            commands::launch::launch(&path).unwrap();
+        Commands::Launch {
+            path,
+            repo,
+            profile,
+            check_deps,
+            ignore,
+            backend,
+            proton,
+            wrapper,
+            par_file,
+            dry_run,
+            platform,
+            workshop,
+            workshop_preset,
+        } => {
+            let wrapper: Option<Vec<String>> =
+                wrapper.map(|wrapper| wrapper.split_whitespace().map(str::to_string).collect());
+
+            let options = commands::launch::Options {
+                repo_name: repo.as_deref(),
+                profile: profile.as_deref(),
+                deps_ignore: check_deps.then_some(ignore.as_slice()),
+                steam_root: steam_root.as_deref(),
+                backend,
+                proton: proton.as_deref(),
+                wrapper: wrapper.as_deref(),
+                par_file,
+                dry_run,
+                platform,
+                workshop: &workshop,
+                workshop_preset: workshop_preset.as_deref(),
+            };
+
+            let code =
+                commands::launch::launch(&mod_path_or_exit(path, steam_root.as_deref()), &options)
+                    .unwrap();
+
+            if code != 0 {
+                std::process::exit(code);
+            }
         }
     }
 }
@@ -1,7 +1,7 @@
 use crate::md5_digest::Md5Digest;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::path::Path;
@@ -21,11 +21,32 @@ pub enum Error {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Mod {
     pub name: String,
+    // names of the repositories that require this mod. mods that were found on disk but never
+    // synced from a repository have no references and are never pruned
+    #[serde(default)]
+    pub repos: BTreeSet<String>,
+    // repositories can ship mods that are disabled by default, launch skips those
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl Mod {
+    pub fn is_referenced_by(&self, repo_name: &str) -> bool {
+        self.repos.contains(repo_name)
+    }
 }
 
 impl From<crate::srf::Mod> for Mod {
     fn from(value: crate::srf::Mod) -> Self {
-        Mod { name: value.name }
+        Mod {
+            name: value.name,
+            repos: BTreeSet::new(),
+            enabled: true,
+        }
     }
 }
 
@@ -35,6 +56,13 @@ type SrfMod = crate::srf::Mod;
 pub struct ModCache {
     version: u32,
     pub mods: HashMap<Md5Digest, Mod>,
+    // mod names in the order each repository lists them, which is the order they are loaded in
+    #[serde(default)]
+    pub load_orders: BTreeMap<String, Vec<String>>,
+    // mod names each repository lists as optional. sync doesn't download them, but players may
+    // have them anyway
+    #[serde(default)]
+    pub optional_mods: BTreeMap<String, Vec<String>>,
 }
 
 impl ModCache {
@@ -42,6 +70,8 @@ impl ModCache {
         Self {
             version: 1,
             mods: mods.into_iter().map(|(k, v)| (k, v.into())).collect(),
+            load_orders: BTreeMap::new(),
+            optional_mods: BTreeMap::new(),
         }
     }
 
@@ -49,6 +79,8 @@ impl ModCache {
         Self {
             version: 1,
             mods: HashMap::new(),
+            load_orders: BTreeMap::new(),
+            optional_mods: BTreeMap::new(),
         }
     }
 
@@ -84,11 +116,168 @@ impl ModCache {
         Ok(())
     }
 
-    pub fn remove(&mut self, checksum: &Md5Digest) {
-        self.mods.remove(checksum);
+    pub fn remove(&mut self, checksum: &Md5Digest) -> Option<Mod> {
+        self.mods.remove(checksum)
+    }
+
+    // removes every entry for a mod name, regardless of version. swifty mod names are case insensitive
+    pub fn remove_by_name(&mut self, name: &str) -> Vec<Mod> {
+        let checksums: Vec<_> = self
+            .mods
+            .iter()
+            .filter(|(_, r#mod)| r#mod.name.eq_ignore_ascii_case(name))
+            .map(|(checksum, _)| checksum.clone())
+            .collect();
+
+        checksums
+            .iter()
+            .filter_map(|checksum| self.mods.remove(checksum))
+            .collect()
+    }
+
+    pub fn insert(&mut self, r#mod: crate::srf::Mod) -> &mut Mod {
+        let checksum = r#mod.checksum.clone();
+        self.mods.insert(checksum.clone(), r#mod.into());
+
+        self.mods.get_mut(&checksum).expect("mod was just inserted")
+    }
+
+    pub fn mods_for_repo<'a>(&'a self, repo_name: &'a str) -> impl Iterator<Item = &'a Mod> {
+        self.mods
+            .values()
+            .filter(move |r#mod| r#mod.is_referenced_by(repo_name))
+    }
+
+    // the mods in storage a repository lists as optional
+    pub fn optional_mods_for_repo<'a>(
+        &'a self,
+        repo_name: &'a str,
+    ) -> impl Iterator<Item = &'a Mod> {
+        let names = self
+            .optional_mods
+            .get(repo_name)
+            .map_or(&[][..], Vec::as_slice);
+
+        self.mods.values().filter(move |r#mod| {
+            names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&r#mod.name))
+        })
+    }
+
+    // the declared load order of a repository, or of every repository one after another. mods
+    // are only listed once, where they first appear
+    pub fn declared_order(&self, repo_name: Option<&str>) -> Vec<&str> {
+        let orders: Vec<&Vec<String>> = match repo_name {
+            Some(repo_name) => self.load_orders.get(repo_name).into_iter().collect(),
+            None => self.load_orders.values().collect(),
+        };
+
+        let mut names: Vec<&str> = Vec::new();
+        for name in orders.into_iter().flatten() {
+            if !names.iter().any(|seen| seen.eq_ignore_ascii_case(name)) {
+                names.push(name);
+            }
+        }
+
+        names
     }
 
-    pub fn insert(&mut self, r#mod: crate::srf::Mod) {
-        self.mods.insert(r#mod.checksum.clone(), r#mod.into());
+    // makes repo_name reference exactly the mods in mod_names, in that order. returns the
+    // checksums of mods that were referenced before but are now not needed by any repository
+    pub fn update_repo_references(
+        &mut self,
+        repo_name: &str,
+        mod_names: &[&str],
+    ) -> Vec<Md5Digest> {
+        let mut orphans = Vec::new();
+
+        self.load_orders.insert(
+            repo_name.to_string(),
+            mod_names.iter().map(ToString::to_string).collect(),
+        );
+
+        for (checksum, r#mod) in &mut self.mods {
+            let required = mod_names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&r#mod.name));
+
+            if required {
+                r#mod.repos.insert(repo_name.to_string());
+            } else if r#mod.repos.remove(repo_name) && r#mod.repos.is_empty() {
+                orphans.push(checksum.clone());
+            }
+        }
+
+        orphans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached_mod(name: &str, repos: &[&str]) -> Mod {
+        Mod {
+            name: name.to_string(),
+            repos: repos.iter().map(ToString::to_string).collect(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn shared_mods_are_not_orphaned() {
+        let mut cache = ModCache::new_empty();
+        cache.mods.insert(
+            Md5Digest::from_bytes([1; 16]),
+            cached_mod("@cba_a3", &["alpha", "bravo"]),
+        );
+        cache.mods.insert(
+            Md5Digest::from_bytes([2; 16]),
+            cached_mod("@alpha_only", &["alpha"]),
+        );
+        cache
+            .mods
+            .insert(Md5Digest::from_bytes([3; 16]), cached_mod("@local", &[]));
+
+        let orphans = cache.update_repo_references("alpha", &["@new_mod"]);
+
+        assert_eq!(orphans, vec![Md5Digest::from_bytes([2; 16])]);
+        assert_eq!(
+            cache.mods[&Md5Digest::from_bytes([1; 16])].repos,
+            BTreeSet::from(["bravo".to_string()])
+        );
+        assert!(cache.mods[&Md5Digest::from_bytes([3; 16])].repos.is_empty());
+    }
+
+    #[test]
+    fn references_are_case_insensitive() {
+        let mut cache = ModCache::new_empty();
+        cache
+            .mods
+            .insert(Md5Digest::from_bytes([1; 16]), cached_mod("@ace", &[]));
+
+        let orphans = cache.update_repo_references("alpha", &["@ACE"]);
+
+        assert!(orphans.is_empty());
+        assert_eq!(cache.mods_for_repo("alpha").count(), 1);
+        assert_eq!(cache.mods_for_repo("bravo").count(), 0);
+    }
+
+    #[test]
+    fn declared_order_test() {
+        let mut cache = ModCache::new_empty();
+        cache.update_repo_references("bravo", &["@cba_a3", "@rhs", "@ace"]);
+        cache.update_repo_references("alpha", &["@CBA_A3", "@ace", "@compat"]);
+
+        assert_eq!(
+            cache.declared_order(Some("bravo")),
+            ["@cba_a3", "@rhs", "@ace"]
+        );
+        assert_eq!(
+            cache.declared_order(None),
+            ["@CBA_A3", "@ace", "@compat", "@rhs"]
+        );
+        assert!(cache.declared_order(Some("charlie")).is_empty());
     }
 }
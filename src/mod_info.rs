@@ -0,0 +1,313 @@
+use snafu::Snafu;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("syntax error on line {}: {}", line, message))]
+    Syntax { line: usize, message: &'static str },
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+
+        c
+    }
+
+    fn error<T>(&self, message: &'static str) -> Result<T, Error> {
+        SyntaxSnafu {
+            line: self.line,
+            message,
+        }
+        .fail()
+    }
+
+    // skips whitespace, comments and preprocessor directives
+    fn skip_trivia(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                c if c.is_whitespace() => {
+                    self.next();
+                }
+                '#' => self.skip_line(),
+                '/' => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+
+                    match lookahead.peek() {
+                        Some('/') => self.skip_line(),
+                        Some('*') => {
+                            self.next();
+                            self.next();
+
+                            let mut previous = None;
+                            while let Some(c) = self.next() {
+                                if previous == Some('*') && c == '/' {
+                                    break;
+                                }
+                                previous = Some(c);
+                            }
+                        }
+                        _ => return,
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut identifier = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+
+            identifier.push(c);
+            self.next();
+        }
+
+        identifier
+    }
+
+    // quotes inside strings are escaped by doubling them
+    fn string(&mut self) -> Result<String, Error> {
+        let mut string = String::new();
+        self.next();
+
+        loop {
+            match self.next() {
+                Some('"') if self.chars.peek() == Some(&'"') => {
+                    self.next();
+                    string.push('"');
+                }
+                Some('"') => return Ok(string),
+                Some(c) => string.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+
+    // arrays are kept as written, nothing needs their elements yet
+    fn array(&mut self) -> Result<String, Error> {
+        let mut array = String::new();
+        let mut depth = 0;
+
+        loop {
+            match self.next() {
+                Some('{') => {
+                    depth += 1;
+                    if depth > 1 {
+                        array.push('{');
+                    }
+                }
+                Some('}') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(array.trim().to_string());
+                    }
+                    array.push('}');
+                }
+                Some(c) => array.push(c),
+                None => return self.error("unterminated array"),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<String, Error> {
+        match self.chars.peek() {
+            Some('"') => self.string(),
+            Some('{') => self.array(),
+            _ => {
+                let mut value = String::new();
+
+                while let Some(&c) = self.chars.peek() {
+                    if c == ';' || c == '\n' {
+                        break;
+                    }
+
+                    value.push(c);
+                    self.next();
+                }
+
+                Ok(value.trim().to_string())
+            }
+        }
+    }
+
+    fn entry(&mut self) -> Result<(String, String), Error> {
+        let name = self.identifier();
+        if name.is_empty() {
+            return self.error("expected a property name");
+        }
+
+        self.skip_trivia();
+        if self.chars.peek() == Some(&'[') {
+            self.next();
+            if self.next() != Some(']') {
+                return self.error("expected ]");
+            }
+            self.skip_trivia();
+        }
+
+        if self.next() != Some('=') {
+            return self.error("expected =");
+        }
+
+        self.skip_trivia();
+        let value = self.value()?;
+        self.skip_trivia();
+
+        // the last semicolon is often left out
+        if self.chars.peek() == Some(&';') {
+            self.next();
+        }
+
+        Ok((name.to_lowercase(), value))
+    }
+}
+
+// parses the flat `name = value;` files mods describe themselves with. names are lowercased, as
+// the game doesn't care about their case
+pub fn parse(input: &str) -> Result<BTreeMap<String, String>, Error> {
+    let mut parser = Parser::new(input);
+    let mut entries = BTreeMap::new();
+
+    loop {
+        parser.skip_trivia();
+        if parser.chars.peek().is_none() {
+            return Ok(entries);
+        }
+
+        let (name, value) = parser.entry()?;
+        entries.insert(name, value);
+    }
+}
+
+fn parse_file(path: &Path) -> Result<BTreeMap<String, String>, Error> {
+    match std::fs::read(path) {
+        Ok(bytes) => parse(&String::from_utf8_lossy(&bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(source) => Err(Error::Io { source }),
+    }
+}
+
+// what mod.cpp and meta.cpp say about a mod. everything is optional, as both files are
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ModInfo {
+    pub name: Option<String>,
+    pub picture: Option<String>,
+    pub author: Option<String>,
+    pub tooltip: Option<String>,
+    pub version: Option<String>,
+    // steam workshop id
+    pub published_id: Option<u64>,
+}
+
+impl ModInfo {
+    pub fn from_entries(
+        mod_cpp: &BTreeMap<String, String>,
+        meta_cpp: &BTreeMap<String, String>,
+    ) -> Self {
+        let get = |entries: &BTreeMap<String, String>, name: &str| {
+            entries.get(name).filter(|value| !value.is_empty()).cloned()
+        };
+
+        Self {
+            name: get(mod_cpp, "name").or_else(|| get(meta_cpp, "name")),
+            picture: get(mod_cpp, "picture"),
+            author: get(mod_cpp, "author"),
+            tooltip: get(mod_cpp, "tooltip"),
+            version: get(mod_cpp, "version"),
+            published_id: get(meta_cpp, "publishedid")
+                .and_then(|id| id.parse().ok())
+                .filter(|id| *id != 0),
+        }
+    }
+
+    // reads mod.cpp and meta.cpp from a mod folder. either of them may be missing
+    pub fn read(mod_path: &Path) -> Result<Self, Error> {
+        let mod_cpp = parse_file(&mod_path.join("mod.cpp"))?;
+        let meta_cpp = parse_file(&mod_path.join("meta.cpp"))?;
+
+        Ok(Self::from_entries(&mod_cpp, &meta_cpp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mod_cpp_test() {
+        let mod_cpp = r#"
+            // ACE3 mod.cpp
+            name = "Advanced Combat Environment 3.15.2";
+            picture = "title_co.paa";
+            actionName = "Website";
+            action = "https://ace3.acemod.org/";
+            /* multi
+               line */
+            tooltip = "ACE3 ""quoted""";
+            overview = "Open-source realism mod";
+            hideName = 1;
+            dlcColor[] = {0.2, 0.4, 0.8, 1};
+            author = "ACE Mod Team"
+        "#;
+
+        let entries = parse(mod_cpp).unwrap();
+        assert_eq!(entries["hidename"], "1");
+        assert_eq!(entries["dlccolor"], "0.2, 0.4, 0.8, 1");
+
+        let meta_cpp = "protocol = 1;\npublishedid = 463939057;\nname = \"ace\";\ntimestamp = 5249155938458398962;\n";
+        let info = ModInfo::from_entries(&entries, &parse(meta_cpp).unwrap());
+
+        assert_eq!(
+            info,
+            ModInfo {
+                name: Some("Advanced Combat Environment 3.15.2".to_string()),
+                picture: Some("title_co.paa".to_string()),
+                author: Some("ACE Mod Team".to_string()),
+                tooltip: Some("ACE3 \"quoted\"".to_string()),
+                version: None,
+                published_id: Some(463939057),
+            }
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        let result = parse("name = \"unterminated;\n");
+
+        assert!(matches!(result, Err(Error::Syntax { line: 2, .. })));
+    }
+}
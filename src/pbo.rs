@@ -2,18 +2,21 @@ use std::ffi::FromVecWithNulError;
 use std::{
     collections::HashMap,
     ffi::CString,
-    io::{BufRead, Seek},
+    io::{BufRead, Read, Seek, SeekFrom},
 };
 
-use byteorder::{LittleEndian, ReadBytesExt};
-use snafu::{ResultExt, Snafu};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha1::{Digest, Sha1};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 #[derive(Debug)]
 pub struct Pbo<I: BufRead + Seek> {
     pub input: I,
     pub header_len: u64,
-    // We parse this but never really use it.
-    #[allow(dead_code)]
     pub extensions: HashMap<String, String>,
     pub entries: Vec<PboEntry>,
 }
@@ -31,9 +34,9 @@ pub struct PboEntry {
     pub filename: String,
     pub r#type: EntryType,
     pub data_size: u32,
-    // We parse this but never really use it.
-    #[allow(dead_code)]
+    // only meaningful for Cprs entries, where data_size is the compressed size
     pub original_size: u32,
+    // reserved, always zero in practice. data is located by summing the previous entries' sizes
     #[allow(dead_code)]
     pub offset: u32,
     #[allow(dead_code)]
@@ -48,23 +51,110 @@ pub enum Error {
     PboType { r#type: u32 },
     #[snafu(display("string deserialization error: {}", source))]
     StringDeserialization { source: FromVecWithNulError },
+    #[snafu(display("entry {} is larger than 4 GiB", name))]
+    EntryTooLarge { name: String },
+    #[snafu(display("{} changed while it was being packed", path.display()))]
+    FileChanged { path: PathBuf },
+    #[snafu(display(
+        "pbo is truncated: expected at least {} bytes, found {}",
+        expected,
+        length
+    ))]
+    Truncated { expected: u64, length: u64 },
+    #[snafu(display("pbo has no trailing checksum"))]
+    MissingChecksum,
+    #[snafu(display("pbo trailer starts with {:#04x} instead of a zero byte", byte))]
+    CorruptTrailer { byte: u8 },
+    #[snafu(display("checksum mismatch: expected {}, got {}", expected, actual))]
+    ChecksumMismatch { expected: String, actual: String },
+    #[snafu(display("entry not found: {}", name))]
+    EntryNotFound { name: String },
+    #[snafu(display("pbo header ends unexpectedly"))]
+    TruncatedHeader,
+    #[snafu(display("unterminated string in pbo header"))]
+    UnterminatedString,
+    #[snafu(display(
+        "entry {} claims {} bytes, but the pbo is only {} bytes long",
+        name,
+        size,
+        length
+    ))]
+    EntryOutOfBounds {
+        name: String,
+        size: u32,
+        length: u64,
+    },
+    #[snafu(display("failed to parse {}: {}", name, source))]
+    Config {
+        name: String,
+        source: crate::rap::Error,
+    },
+    #[snafu(display("failed to decompress {}: {}", name, source))]
+    Decompression {
+        name: String,
+        source: crate::lzss::Error,
+    },
 }
 
+// no real header string comes close, this just stops us from reading a whole garbage file
+const MAX_STRING_LEN: u64 = 4096;
+
 fn read_string<I: BufRead + Seek>(input: &mut I) -> Result<String, Error> {
     let mut buf = Vec::new();
 
-    input.read_until(b'\0', &mut buf).context(IoSnafu {})?;
+    input
+        .by_ref()
+        .take(MAX_STRING_LEN)
+        .read_until(b'\0', &mut buf)
+        .context(IoSnafu {})?;
+    if buf.last() != Some(&0) {
+        return match buf.len() as u64 {
+            MAX_STRING_LEN => UnterminatedStringSnafu.fail(),
+            _ => TruncatedHeaderSnafu.fail(),
+        };
+    }
 
     let cstring = CString::from_vec_with_nul(buf).context(StringDeserializationSnafu)?;
 
     Ok(cstring.to_string_lossy().to_string())
 }
 
+fn read_u32<I: BufRead + Seek>(input: &mut I) -> Result<u32, Error> {
+    input
+        .read_u32::<LittleEndian>()
+        .map_err(|source| match source.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::TruncatedHeader,
+            _ => Error::Io { source },
+        })
+}
+
+impl EntryType {
+    fn magic(&self) -> u32 {
+        match self {
+            Self::Vers => 0x56657273,
+            Self::Cprs => 0x43707273,
+            Self::Enco => 0x456e6372,
+            Self::None => 0x00000000,
+        }
+    }
+}
+
 impl PboEntry {
+    // the game compares entry names case insensitively, and they're stored with backslashes
+    pub fn matches(&self, name: &str) -> bool {
+        self.filename
+            .replace('\\', "/")
+            .eq_ignore_ascii_case(&name.replace('\\', "/"))
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.r#type == EntryType::Cprs
+    }
+
     fn read<I: BufRead + Seek>(input: &mut I) -> Result<Self, Error> {
         let filename = read_string(input)?;
 
-        let r#type = input.read_u32::<LittleEndian>().context(IoSnafu {})?;
+        let r#type = read_u32(input)?;
 
         let r#type = match r#type {
             0x56657273 => EntryType::Vers,
@@ -74,10 +164,10 @@ impl PboEntry {
             _ => return Err(Error::PboType { r#type }),
         };
 
-        let original_size = input.read_u32::<LittleEndian>().context(IoSnafu {})?;
-        let offset = input.read_u32::<LittleEndian>().context(IoSnafu {})?;
-        let timestamp = input.read_u32::<LittleEndian>().context(IoSnafu {})?;
-        let data_size = input.read_u32::<LittleEndian>().context(IoSnafu {})?;
+        let original_size = read_u32(input)?;
+        let offset = read_u32(input)?;
+        let timestamp = read_u32(input)?;
+        let data_size = read_u32(input)?;
 
         Ok(PboEntry {
             filename,
@@ -108,6 +198,9 @@ fn read_extensions<I: BufRead + Seek>(input: &mut I) -> Result<HashMap<String, S
 
 impl<I: BufRead + Seek> Pbo<I> {
     pub fn read(mut input: I) -> Result<Self, Error> {
+        let length = input.seek(SeekFrom::End(0)).context(IoSnafu {})?;
+        input.seek(SeekFrom::Start(0)).context(IoSnafu {})?;
+
         let mut extensions = HashMap::new();
 
         let mut entries = Vec::new();
@@ -123,17 +216,369 @@ impl<I: BufRead + Seek> Pbo<I> {
                 extensions = read_extensions(&mut input)?;
             }
 
+            // catches garbage early, before the sizes are summed up
+            ensure!(
+                u64::from(entry.data_size) <= length,
+                EntryOutOfBoundsSnafu {
+                    name: &entry.filename,
+                    size: entry.data_size,
+                    length
+                }
+            );
+
             entries.push(entry);
         }
 
-        let header_len = input.stream_position().unwrap();
+        let header_len = input.stream_position().context(IoSnafu {})?;
 
-        Ok(Pbo {
+        let pbo = Pbo {
             input,
             header_len,
             extensions,
             entries,
-        })
+        };
+
+        let data_end = pbo.data_offset(pbo.entries.len());
+        ensure!(
+            data_end <= length,
+            TruncatedSnafu {
+                expected: data_end,
+                length
+            }
+        );
+
+        Ok(pbo)
+    }
+
+    // the in-game path of the addon. pbos without a prefix extension are mounted under their file name
+    pub fn prefix(&self) -> Option<&str> {
+        self.extensions
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("prefix"))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // entry data is stored back to back right after the header, in the same order as the entries
+    pub fn data_offset(&self, index: usize) -> u64 {
+        self.header_len
+            + self.entries[..index]
+                .iter()
+                .map(|entry| u64::from(entry.data_size))
+                .sum::<u64>()
+    }
+
+    pub fn entry_index(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.matches(name))
+    }
+
+    pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let index = self
+            .entry_index(name)
+            .context(EntryNotFoundSnafu { name })?;
+
+        self.read_entry_at(index)
+    }
+
+    // the binarized config of the addon, if it has one. plain text config.cpp files aren't parsed
+    pub fn read_config(&mut self) -> Result<Option<crate::rap::Class>, Error> {
+        let Some(index) = self.entry_index("config.bin") else {
+            return Ok(None);
+        };
+
+        let data = self.read_entry_at(index)?;
+        let config = crate::rap::read(std::io::Cursor::new(data)).context(ConfigSnafu {
+            name: &self.entries[index].filename,
+        })?;
+
+        Ok(Some(config))
+    }
+
+    // SHA-1 of the header and all entry data, which is what the trailing checksum should contain
+    pub fn hash(&mut self) -> Result<[u8; 20], Error> {
+        let data_end = self.data_offset(self.entries.len());
+
+        self.input.seek(SeekFrom::Start(0)).context(IoSnafu {})?;
+        let mut hasher = Sha1::new();
+        std::io::copy(&mut (&mut self.input).take(data_end), &mut hasher).context(IoSnafu {})?;
+
+        Ok(hasher.finalize().into())
+    }
+
+    // arma pbos end with a zero byte followed by the SHA-1 of everything before it
+    pub fn verify(&mut self) -> Result<(), Error> {
+        let data_end = self.data_offset(self.entries.len());
+        let length = self.input.seek(SeekFrom::End(0)).context(IoSnafu {})?;
+
+        ensure!(
+            length >= data_end,
+            TruncatedSnafu {
+                expected: data_end,
+                length
+            }
+        );
+        ensure!(length != data_end, MissingChecksumSnafu);
+        ensure!(
+            length >= data_end + 21,
+            TruncatedSnafu {
+                expected: data_end + 21,
+                length
+            }
+        );
+
+        let actual = self.hash()?;
+
+        let mut trailer = [0; 21];
+        self.input.read_exact(&mut trailer).context(IoSnafu {})?;
+        ensure!(trailer[0] == 0, CorruptTrailerSnafu { byte: trailer[0] });
+
+        ensure!(
+            trailer[1..] == actual[..],
+            ChecksumMismatchSnafu {
+                expected: hex::encode_upper(&trailer[1..]),
+                actual: hex::encode_upper(actual),
+            }
+        );
+
+        Ok(())
+    }
+
+    // returns the entry's contents, decompressing them if needed
+    pub fn read_entry_at(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        let data = self.read_stored_at(index)?;
+        let entry = &self.entries[index];
+
+        if entry.is_compressed() {
+            return crate::lzss::decompress(&mut data.as_slice(), entry.original_size as usize)
+                .context(DecompressionSnafu {
+                    name: &entry.filename,
+                });
+        }
+
+        Ok(data)
+    }
+
+    // returns the entry's data as it is stored in the pbo
+    pub fn read_stored_at(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        let offset = self.data_offset(index);
+        let entry = &self.entries[index];
+
+        self.input
+            .seek(SeekFrom::Start(offset))
+            .context(IoSnafu {})?;
+
+        let mut buf = vec![0; entry.data_size as usize];
+        self.input.read_exact(&mut buf).context(IoSnafu {})?;
+
+        Ok(buf)
+    }
+}
+
+pub fn verify_file<I: BufRead + Seek>(input: I) -> Result<(), Error> {
+    Pbo::read(input)?.verify()
+}
+
+enum EntryData {
+    // no command generates entries yet, only tests do
+    #[cfg(test)]
+    Memory(Vec<u8>),
+    File(PathBuf),
+}
+
+struct WriterEntry {
+    filename: String,
+    timestamp: u32,
+    size: u32,
+    data: EntryData,
+}
+
+// writes everything through to the output while hashing it for the trailing checksum
+struct HashingWriter<O: Write> {
+    output: O,
+    hasher: Sha1,
+}
+
+impl<O: Write> Write for HashingWriter<O> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.output.write(buf)?;
+        self.hasher.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+fn write_string<O: Write>(output: &mut O, string: &str) -> Result<(), Error> {
+    output.write_all(string.as_bytes()).context(IoSnafu {})?;
+    output.write_u8(0).context(IoSnafu {})
+}
+
+fn write_entry_header<O: Write>(
+    output: &mut O,
+    filename: &str,
+    r#type: &EntryType,
+    size: u32,
+    timestamp: u32,
+) -> Result<(), Error> {
+    write_string(output, filename)?;
+
+    for field in [r#type.magic(), size, 0, timestamp, size] {
+        output
+            .write_u32::<LittleEndian>(field)
+            .context(IoSnafu {})?;
+    }
+
+    Ok(())
+}
+
+// packs uncompressed entries into an arma compatible pbo: a Vers entry carrying the extensions, the
+// entry table, the entry data and finally a zero byte followed by the SHA-1 of everything before it
+#[derive(Default)]
+pub struct PboWriter {
+    extensions: Vec<(String, String)>,
+    entries: Vec<WriterEntry>,
+}
+
+impl PboWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extension(&mut self, key: &str, value: &str) -> &mut Self {
+        self.extensions.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn add_data(&mut self, filename: &str, data: Vec<u8>, timestamp: u32) -> Result<(), Error> {
+        let size = u32::try_from(data.len())
+            .ok()
+            .context(EntryTooLargeSnafu { name: filename })?;
+
+        self.entries.push(WriterEntry {
+            filename: filename.replace('/', "\\"),
+            timestamp,
+            size,
+            data: EntryData::Memory(data),
+        });
+
+        Ok(())
+    }
+
+    // files are only read when writing, so packing large addons doesn't need them all in memory
+    pub fn add_file(&mut self, filename: &str, path: &Path) -> Result<(), Error> {
+        let metadata = std::fs::metadata(path).context(IoSnafu {})?;
+
+        let size = u32::try_from(metadata.len())
+            .ok()
+            .context(EntryTooLargeSnafu { name: filename })?;
+
+        let timestamp = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs() as u32);
+
+        self.entries.push(WriterEntry {
+            filename: filename.replace('/', "\\"),
+            timestamp,
+            size,
+            data: EntryData::File(path.to_owned()),
+        });
+
+        Ok(())
+    }
+
+    // adds every file in dir. $PBOPREFIX$ becomes the prefix extension unless one was already set
+    pub fn add_dir(&mut self, dir: &Path) -> Result<(), Error> {
+        let mut files: Vec<_> = walkdir::WalkDir::new(dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .map(|e| {
+                let relative = e
+                    .path()
+                    .strip_prefix(dir)
+                    .expect("walkdir returned a path outside of dir")
+                    .to_string_lossy()
+                    .replace('/', "\\");
+
+                (relative, e.into_path())
+            })
+            .collect();
+
+        // other packers sort entries case insensitively, so we do too
+        files.sort_by_key(|(relative, _)| relative.to_lowercase());
+
+        for (relative, path) in files {
+            if relative == "$PBOPREFIX$" {
+                if !self.extensions.iter().any(|(key, _)| key == "prefix") {
+                    let prefix = std::fs::read_to_string(&path).context(IoSnafu {})?;
+                    self.extension("prefix", prefix.trim());
+                }
+
+                continue;
+            }
+
+            self.add_file(&relative, &path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write<O: Write>(&self, output: O) -> Result<(), Error> {
+        let mut output = HashingWriter {
+            output,
+            hasher: Sha1::new(),
+        };
+
+        write_entry_header(&mut output, "", &EntryType::Vers, 0, 0)?;
+
+        for (key, value) in &self.extensions {
+            write_string(&mut output, key)?;
+            write_string(&mut output, value)?;
+        }
+        write_string(&mut output, "")?;
+
+        for entry in &self.entries {
+            write_entry_header(
+                &mut output,
+                &entry.filename,
+                &EntryType::None,
+                entry.size,
+                entry.timestamp,
+            )?;
+        }
+        write_entry_header(&mut output, "", &EntryType::None, 0, 0)?;
+
+        for entry in &self.entries {
+            match &entry.data {
+                #[cfg(test)]
+                EntryData::Memory(data) => output.write_all(data).context(IoSnafu {})?,
+                EntryData::File(path) => {
+                    let mut file = File::open(path).context(IoSnafu {})?;
+                    let copied =
+                        std::io::copy(&mut (&mut file).take(u64::from(entry.size)), &mut output)
+                            .context(IoSnafu {})?;
+
+                    // the entry table was already written with the old size, so it can neither
+                    // have shrunk nor grown since
+                    let grew = file.read(&mut [0]).context(IoSnafu {})? != 0;
+                    ensure!(
+                        copied == u64::from(entry.size) && !grew,
+                        FileChangedSnafu { path }
+                    );
+                }
+            }
+        }
+
+        let HashingWriter { mut output, hasher } = output;
+
+        output.write_u8(0).context(IoSnafu {})?;
+        output.write_all(&hasher.finalize()).context(IoSnafu {})?;
+        output.flush().context(IoSnafu {})
     }
 }
 
@@ -148,4 +593,123 @@ mod tests {
         let pbo = Pbo::read(Cursor::new(&bytes)).unwrap();
         assert_eq!(pbo.entries.len(), 49);
     }
+
+    #[test]
+    fn read_entry_test() {
+        let bytes = include_bytes!("../test_files/@ace/addons/ace_advanced_ballistics.pbo");
+        let mut pbo = Pbo::read(Cursor::new(&bytes)).unwrap();
+
+        let config = pbo.read_entry("config.bin").unwrap();
+        assert_eq!(config.len(), 4109);
+        assert_eq!(&config[..4], b"\0raP");
+
+        let script = pbo.read_entry("functions/script_component.hpp").unwrap();
+        assert_eq!(script.len(), 67);
+
+        assert!(matches!(
+            pbo.read_entry("missing.sqf"),
+            Err(Error::EntryNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_test() {
+        let bytes = include_bytes!("../test_files/@ace/addons/ace_advanced_ballistics.pbo");
+        let mut pbo = Pbo::read(Cursor::new(&bytes)).unwrap();
+        pbo.verify().unwrap();
+
+        let mut tampered = bytes.to_vec();
+        tampered[5000] ^= 0xFF;
+        let mut pbo = Pbo::read(Cursor::new(&tampered)).unwrap();
+        assert!(matches!(pbo.verify(), Err(Error::ChecksumMismatch { .. })));
+
+        let truncated = &bytes[..bytes.len() - 10];
+        let mut pbo = Pbo::read(Cursor::new(truncated)).unwrap();
+        assert!(matches!(pbo.verify(), Err(Error::Truncated { .. })));
+
+        let mut corrupt_trailer = bytes.to_vec();
+        corrupt_trailer[bytes.len() - 21] = 1;
+        let mut pbo = Pbo::read(Cursor::new(&corrupt_trailer)).unwrap();
+        assert!(matches!(
+            pbo.verify(),
+            Err(Error::CorruptTrailer { byte: 1 })
+        ));
+
+        let unsigned = &bytes[..bytes.len() - 21];
+        let mut pbo = Pbo::read(Cursor::new(unsigned)).unwrap();
+        assert!(matches!(pbo.verify(), Err(Error::MissingChecksum)));
+    }
+
+    #[test]
+    fn corrupt_test() {
+        let read = |bytes: &[u8]| Pbo::read(Cursor::new(bytes.to_vec())).map(|_| ());
+
+        assert!(matches!(
+            read(include_bytes!(
+                "../test_files/@corrupt/addons/truncated.pbo"
+            )),
+            Err(Error::Truncated { .. })
+        ));
+        assert!(matches!(
+            read(include_bytes!("../test_files/@corrupt/addons/garbage.pbo")),
+            Err(Error::UnterminatedString)
+        ));
+        assert!(matches!(
+            read(include_bytes!(
+                "../test_files/@corrupt/addons/unknown_type.pbo"
+            )),
+            Err(Error::PboType { .. })
+        ));
+        assert!(matches!(
+            read(include_bytes!(
+                "../test_files/@corrupt/addons/oversized.pbo"
+            )),
+            Err(Error::EntryOutOfBounds { .. })
+        ));
+
+        let bytes = include_bytes!("../test_files/@ace/addons/ace_advanced_ballistics.pbo");
+        assert!(matches!(read(&bytes[..100]), Err(Error::TruncatedHeader)));
+    }
+
+    #[test]
+    fn writer_roundtrip_test() {
+        let bytes = include_bytes!("../test_files/@ace/addons/ace_advanced_ballistics.pbo");
+        let mut pbo = Pbo::read(Cursor::new(&bytes)).unwrap();
+
+        let mut writer = PboWriter::new();
+        for key in ["prefix", "Mikero", "version"] {
+            writer.extension(key, &pbo.extensions[key]);
+        }
+
+        for index in 1..pbo.entries.len() {
+            let data = pbo.read_entry_at(index).unwrap();
+            let entry = &pbo.entries[index];
+            writer
+                .add_data(&entry.filename, data, entry.timestamp)
+                .unwrap();
+        }
+
+        let mut packed = Vec::new();
+        writer.write(&mut packed).unwrap();
+
+        assert_eq!(packed, bytes);
+    }
+
+    #[test]
+    fn writer_file_changed_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.cpp");
+        std::fs::write(&path, "class CfgPatches {};").unwrap();
+
+        let mut writer = PboWriter::new();
+        writer.add_file("config.cpp", &path).unwrap();
+
+        std::fs::write(&path, "class CfgPatches {}; // and then some").unwrap();
+        let result = writer.write(Vec::new());
+        assert!(matches!(result, Err(Error::FileChanged { .. })));
+
+        std::fs::write(&path, "class").unwrap();
+        let result = writer.write(Vec::new());
+        assert!(matches!(result, Err(Error::FileChanged { .. })));
+    }
 }
@@ -0,0 +1,245 @@
+use snafu::{ensure, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("not an Arma 3 Launcher preset"))]
+    NotAPreset,
+}
+
+// a mod as the launcher lists it in a preset
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresetMod {
+    pub display_name: String,
+    // set for workshop mods
+    pub published_id: Option<u64>,
+    // set for local mods, the folder name the launcher knew them by
+    pub local_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: Option<String>,
+    pub mods: Vec<PresetMod>,
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// the text between start and the next end, if start appears at all
+fn between<'a>(input: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let from = input.find(start)? + start.len();
+    let to = input[from..].find(end)?;
+
+    Some(&input[from..from + to])
+}
+
+fn meta<'a>(html: &'a str, name: &str) -> Option<&'a str> {
+    let tag = between(html, &format!("<meta name=\"{name}\""), ">")?;
+
+    between(tag, "content=\"", "\"")
+}
+
+fn parse_row(row: &str) -> PresetMod {
+    let display_name = between(row, "data-type=\"DisplayName\">", "</td>")
+        .map(|name| unescape(name.trim()))
+        .unwrap_or_default();
+
+    let published_id = between(row, "?id=", "\"").and_then(|id| id.parse().ok());
+
+    // data-meta="local:<folder>|<folder>|"
+    let local_name = between(row, "data-meta=\"local:", "\"")
+        .and_then(|meta| meta.split('|').next())
+        .filter(|name| !name.is_empty())
+        .map(unescape);
+
+    PresetMod {
+        display_name,
+        published_id,
+        local_name,
+    }
+}
+
+// reads the html files the launcher exports presets as. they are generated from a template, so
+// looking for the few attributes we care about is enough
+pub fn parse(html: &str) -> Result<Preset, Error> {
+    ensure!(meta(html, "arma:Type") == Some("preset"), NotAPresetSnafu);
+
+    let mods = html
+        .split("<tr")
+        .filter(|row| row.starts_with(" data-type=\"ModContainer\""))
+        .map(parse_row)
+        .collect();
+
+    Ok(Preset {
+        name: meta(html, "arma:PresetName").map(unescape),
+        mods,
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn mod_row(r#mod: &PresetMod) -> String {
+    let (source, link) = match (r#mod.published_id, &r#mod.local_name) {
+        (Some(id), _) => {
+            let url = format!("https://steamcommunity.com/sharedfiles/filedetails/?id={id}");
+
+            (
+                r#"<span class="from-steam">Steam</span>"#.to_string(),
+                format!(r#"<a href="{url}" data-type="Link">{url}</a>"#),
+            )
+        }
+        (None, local_name) => {
+            let local_name = escape(local_name.as_deref().unwrap_or(&r#mod.display_name));
+
+            (
+                r#"<span class="from-local">Local</span>"#.to_string(),
+                format!(
+                    r#"<span data-type="Link" data-meta="local:{local_name}|{local_name}|"></span>"#
+                ),
+            )
+        }
+    };
+
+    format!(
+        r#"        <tr data-type="ModContainer">
+          <td data-type="DisplayName">{}</td>
+          <td>
+            {source}
+          </td>
+          <td>
+            {link}
+          </td>
+        </tr>
+"#,
+        escape(&r#mod.display_name)
+    )
+}
+
+// writes a preset the way the launcher exports them, which is also what it expects to import
+pub fn to_html(preset: &Preset) -> String {
+    let name = escape(preset.name.as_deref().unwrap_or_default());
+    let rows: String = preset.mods.iter().map(mod_row).collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<html>
+  <!--Created by Nimble-->
+  <head>
+    <meta name="arma:Type" content="preset" />
+    <meta name="arma:PresetName" content="{name}" />
+    <meta name="generator" content="Nimble" />
+    <title>Arma 3</title>
+    <style>
+body {{
+	margin: 0;
+	padding: 0;
+	color: #fff;
+	background: #000;
+}}
+
+td {{
+	padding: 3px 30px 3px 0;
+}}
+
+a {{
+	color: #D18F21;
+}}
+    </style>
+  </head>
+  <body>
+    <h1>Arma 3  - Preset <strong>{name}</strong></h1>
+    <p class="before-list">
+      <em>To import this preset, drag this file onto the Launcher window. Or click the MODS tab, then PRESET in the top right, then IMPORT at the bottom, and finally select this file.</em>
+    </p>
+    <div class="mod-list">
+      <table>
+{rows}      </table>
+    </div>
+    <div class="dlc-list">
+      <table />
+    </div>
+  </body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let path = [
+            env!("CARGO_MANIFEST_DIR"),
+            "test_files",
+            "presets",
+            "operation.html",
+        ]
+        .iter()
+        .collect::<std::path::PathBuf>();
+        let preset = parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        assert_eq!(preset.name.as_deref(), Some("Operation & Training"));
+        assert_eq!(
+            preset.mods,
+            [
+                PresetMod {
+                    display_name: "CBA_A3".to_string(),
+                    published_id: Some(450814997),
+                    local_name: None,
+                },
+                PresetMod {
+                    display_name: "ace".to_string(),
+                    published_id: Some(463939057),
+                    local_name: None,
+                },
+                PresetMod {
+                    display_name: "Unit Compat & Fixes".to_string(),
+                    published_id: None,
+                    local_name: Some("@unit_compat".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let preset = Preset {
+            name: Some("<Ops>".to_string()),
+            mods: vec![
+                PresetMod {
+                    display_name: "Advanced Combat Environment".to_string(),
+                    published_id: Some(463939057),
+                    local_name: None,
+                },
+                PresetMod {
+                    display_name: "Compat \"patches\" & fixes".to_string(),
+                    published_id: None,
+                    local_name: Some("@compat".to_string()),
+                },
+            ],
+        };
+
+        assert_eq!(parse(&to_html(&preset)).unwrap(), preset);
+    }
+
+    #[test]
+    fn not_a_preset() {
+        assert!(matches!(
+            parse("<html><body>hello</body></html>"),
+            Err(Error::NotAPreset)
+        ));
+    }
+}
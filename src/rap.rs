@@ -0,0 +1,335 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use snafu::{ensure, ResultExt, Snafu};
+use std::io::{BufRead, Seek, SeekFrom};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("not a rapified config"))]
+    Signature,
+    #[snafu(display("unknown entry type {} at {:#x}", r#type, offset))]
+    EntryType { r#type: u8, offset: u64 },
+    #[snafu(display("unknown value type {} at {:#x}", r#type, offset))]
+    ValueType { r#type: u8, offset: u64 },
+    #[snafu(display("classes or arrays are nested too deeply at {:#x}", offset))]
+    TooDeep { offset: u64 },
+}
+
+// binarized configs never get anywhere near this, it only guards against offsets pointing back
+// into a parent class and arrays nested deep enough to overflow the stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Float(f32),
+    Int(i32),
+    Array(Vec<Value>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Class(Class),
+    Value(Value),
+    // `name[] += {...}`
+    Append(Vec<Value>),
+    // `class name;`
+    Extern,
+    // `delete name;`
+    Delete,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Class {
+    // empty if the class doesn't inherit from anything
+    pub parent: String,
+    pub entries: Vec<(String, Entry)>,
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Self::Float(value) => Some(*value),
+            Self::Int(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl Class {
+    // config names are case insensitive
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|(entry_name, _)| entry_name.eq_ignore_ascii_case(name))
+            .map(|(_, entry)| entry)
+    }
+
+    pub fn class(&self, name: &str) -> Option<&Class> {
+        match self.get(name) {
+            Some(Entry::Class(class)) => Some(class),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        match self.get(name) {
+            Some(Entry::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = (&str, &Class)> {
+        self.entries.iter().filter_map(|(name, entry)| match entry {
+            Entry::Class(class) => Some((name.as_str(), class)),
+            _ => None,
+        })
+    }
+
+    // arrays of strings such as requiredAddons, anything else in them is skipped
+    pub fn strings(&self, name: &str) -> Vec<String> {
+        self.value(name)
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+fn read_string<I: BufRead>(input: &mut I) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    input.read_until(b'\0', &mut buf).context(IoSnafu)?;
+
+    if buf.pop() != Some(0) {
+        return Err(Error::Io {
+            source: std::io::ErrorKind::UnexpectedEof.into(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+// 7 bits at a time, least significant first, the high bit says whether another byte follows
+fn read_compressed_int<I: BufRead>(input: &mut I) -> Result<u32, Error> {
+    let mut value = 0u32;
+
+    for shift in (0..32).step_by(7) {
+        let byte = input.read_u8().context(IoSnafu)?;
+        value |= u32::from(byte & 0x7F) << shift;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    Ok(value)
+}
+
+fn read_value<I: BufRead + Seek>(input: &mut I, r#type: u8, depth: usize) -> Result<Value, Error> {
+    match r#type {
+        0 | 4 => Ok(Value::String(read_string(input)?)),
+        1 => Ok(Value::Float(
+            input.read_f32::<LittleEndian>().context(IoSnafu)?,
+        )),
+        2 => Ok(Value::Int(
+            input.read_i32::<LittleEndian>().context(IoSnafu)?,
+        )),
+        3 => Ok(Value::Array(read_array(input, depth + 1)?)),
+        _ => ValueTypeSnafu {
+            r#type,
+            offset: input.stream_position().context(IoSnafu)?,
+        }
+        .fail(),
+    }
+}
+
+fn read_array<I: BufRead + Seek>(input: &mut I, depth: usize) -> Result<Vec<Value>, Error> {
+    ensure!(
+        depth < MAX_DEPTH,
+        TooDeepSnafu {
+            offset: input.stream_position().context(IoSnafu)?
+        }
+    );
+
+    let count = read_compressed_int(input)?;
+    let mut values = Vec::new();
+
+    for _ in 0..count {
+        let r#type = input.read_u8().context(IoSnafu)?;
+        values.push(read_value(input, r#type, depth)?);
+    }
+
+    Ok(values)
+}
+
+fn read_class<I: BufRead + Seek>(input: &mut I, depth: usize) -> Result<Class, Error> {
+    ensure!(
+        depth < MAX_DEPTH,
+        TooDeepSnafu {
+            offset: input.stream_position().context(IoSnafu)?
+        }
+    );
+
+    let parent = read_string(input)?;
+    let count = read_compressed_int(input)?;
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        let offset = input.stream_position().context(IoSnafu)?;
+        let r#type = input.read_u8().context(IoSnafu)?;
+
+        let entry = match r#type {
+            0 => {
+                let name = read_string(input)?;
+                let body = input.read_u32::<LittleEndian>().context(IoSnafu)?;
+
+                // class bodies are stored elsewhere, come back once it's read
+                let next = input.stream_position().context(IoSnafu)?;
+                input
+                    .seek(SeekFrom::Start(u64::from(body)))
+                    .context(IoSnafu)?;
+                let class = read_class(input, depth + 1)?;
+                input.seek(SeekFrom::Start(next)).context(IoSnafu)?;
+
+                (name, Entry::Class(class))
+            }
+            1 => {
+                let value_type = input.read_u8().context(IoSnafu)?;
+                let name = read_string(input)?;
+
+                (name, Entry::Value(read_value(input, value_type, depth)?))
+            }
+            2 => {
+                let name = read_string(input)?;
+
+                (
+                    name,
+                    Entry::Value(Value::Array(read_array(input, depth + 1)?)),
+                )
+            }
+            3 => (read_string(input)?, Entry::Extern),
+            4 => (read_string(input)?, Entry::Delete),
+            5 => {
+                // always 1, what else it could be is anyone's guess
+                input.read_u32::<LittleEndian>().context(IoSnafu)?;
+                let name = read_string(input)?;
+
+                (name, Entry::Append(read_array(input, depth + 1)?))
+            }
+            _ => return EntryTypeSnafu { r#type, offset }.fail(),
+        };
+
+        entries.push(entry);
+    }
+
+    Ok(Class { parent, entries })
+}
+
+// reads a binarized config (config.bin) into the root class
+pub fn read<I: BufRead + Seek>(mut input: I) -> Result<Class, Error> {
+    let mut signature = [0; 4];
+    input.read_exact(&mut signature).context(IoSnafu)?;
+    ensure!(&signature == b"\0raP", SignatureSnafu);
+
+    // two constant fields and the offset of the enum table, which nothing here needs
+    input.seek(SeekFrom::Current(12)).context(IoSnafu)?;
+
+    read_class(&mut input, 0)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    pub name: String,
+    pub required_addons: Vec<String>,
+    pub units: Vec<String>,
+    pub required_version: Option<f32>,
+}
+
+// every addon registers itself as a CfgPatches class, which is what requiredAddons refers to
+pub fn cfg_patches(config: &Class) -> Vec<Patch> {
+    let Some(patches) = config.class("CfgPatches") else {
+        return Vec::new();
+    };
+
+    patches
+        .classes()
+        .map(|(name, patch)| Patch {
+            name: name.to_string(),
+            required_addons: patch.strings("requiredAddons"),
+            units: patch.strings("units"),
+            required_version: patch.value("requiredVersion").and_then(Value::as_f32),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pbo::Pbo;
+    use std::io::Cursor;
+
+    #[test]
+    fn cfg_patches_test() {
+        let bytes = include_bytes!("../test_files/@ace/addons/ace_advanced_ballistics.pbo");
+        let mut pbo = Pbo::read(Cursor::new(&bytes)).unwrap();
+        let config = read(Cursor::new(pbo.read_entry("config.bin").unwrap())).unwrap();
+
+        assert_eq!(
+            cfg_patches(&config),
+            vec![Patch {
+                name: "ace_advanced_ballistics".to_string(),
+                required_addons: vec!["ace_ballistics".to_string(), "ace_weather".to_string()],
+                units: Vec::new(),
+                required_version: Some(2.1),
+            }]
+        );
+
+        let version = config
+            .class("CfgPatches")
+            .and_then(|patches| patches.class("ACE_ADVANCED_BALLISTICS"))
+            .and_then(|patch| patch.value("versionStr"))
+            .and_then(Value::as_str);
+        assert_eq!(version, Some("3.15.2.69"));
+    }
+
+    #[test]
+    fn nested_arrays() {
+        let mut config = b"\0raP".to_vec();
+        config.extend([0; 12]);
+
+        // root class with a single array entry, holding an array, holding an array...
+        config.extend(b"\0\x01\x02a\0");
+        for _ in 0..100_000 {
+            config.extend([0x01, 0x03]);
+        }
+        config.push(0x00);
+
+        let result = read(Cursor::new(config));
+
+        assert!(matches!(result, Err(Error::TooDeep { .. })));
+    }
+
+    #[test]
+    fn not_rapified() {
+        let result = read(Cursor::new(b"class CfgPatches {};".to_vec()));
+
+        assert!(matches!(result, Err(Error::Signature)));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::md5_digest::Md5Digest;
+use crate::source::Source;
 use serde::{Deserialize, Deserializer, Serialize};
 use snafu::prelude::*;
 use std::{fmt::Display, net::IpAddr, str::FromStr};
@@ -6,14 +7,9 @@ use std::{fmt::Display, net::IpAddr, str::FromStr};
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Error while requesting repository data: {}", source))]
-    Http {
-        url: String,
-
-        #[snafu(source(from(ureq::Error, Box::new)))]
-        source: Box<ureq::Error>,
-    },
+    Source { source: crate::source::Error },
     #[snafu(display("Error while deserializing: {}", source))]
-    Deserialization { source: std::io::Error },
+    Deserialization { source: serde_json::Error },
 }
 
 pub fn deserialize_number_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -75,11 +71,8 @@ pub struct Repository {
     pub servers: Vec<Server>,
 }
 
-pub fn get_repository_info(agent: &mut ureq::Agent, url: &str) -> Result<Repository, Error> {
-    agent
-        .get(url)
-        .call()
-        .context(HttpSnafu { url })?
-        .into_json()
-        .context(DeserializationSnafu)
+pub fn get_repository_info(source: &Source) -> Result<Repository, Error> {
+    let download = source.open("repo.json").context(SourceSnafu)?;
+
+    serde_json::from_reader(download.reader).context(DeserializationSnafu)
 }
@@ -0,0 +1,418 @@
+use crate::pbo::{self, Pbo};
+use byteorder::{LittleEndian, ReadBytesExt};
+use num_bigint::BigUint;
+use sha1::{Digest, Sha1};
+use snafu::{ensure, ResultExt, Snafu};
+use std::io::{BufRead, Read, Seek};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("io error: {}", source))]
+    Io { source: std::io::Error },
+    #[snafu(display("not an RSA public key"))]
+    InvalidKey,
+    #[snafu(display("unknown signature version {}", version))]
+    UnknownVersion { version: u32 },
+    #[snafu(display("failed to read pbo: {}", source))]
+    PboRead { source: pbo::Error },
+    #[snafu(display("signed by {}, but the key belongs to {}", signed_by, key))]
+    AuthorityMismatch { signed_by: String, key: String },
+    #[snafu(display("key {} does not match the one the pbo was signed with", key))]
+    KeyMismatch { key: String },
+    #[snafu(display("signature {} does not match the pbo", index))]
+    InvalidSignature { index: usize },
+}
+
+// the blob header of a CryptoAPI RSA public key, followed by "RSA1"
+const PUBLIC_KEY_BLOB: [u8; 8] = [0x06, 0x02, 0x00, 0x00, 0x00, 0x24, 0x00, 0x00];
+
+// files that are part of the third hash. v2 hashes everything but these instead
+const V2_EXCLUDED: &[&str] = &[
+    "paa", "jpg", "p3d", "tga", "rvmat", "lip", "ogg", "wss", "png", "rtm", "pac", "fxy", "wrp",
+];
+const V3_INCLUDED: &[&str] = &[
+    "sqf", "inc", "bikb", "ext", "fsm", "sqm", "hpp", "cfg", "sqs", "h",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicKey {
+    pub exponent: u32,
+    pub modulus: BigUint,
+    // in bytes
+    pub length: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BiKey {
+    pub authority: String,
+    pub key: PublicKey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V2,
+    V3,
+}
+
+#[derive(Debug, Clone)]
+pub struct BiSign {
+    pub authority: String,
+    pub key: PublicKey,
+    pub version: Version,
+    pub signatures: [BigUint; 3],
+}
+
+fn read_string<I: BufRead>(input: &mut I) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    input.read_until(b'\0', &mut buf).context(IoSnafu)?;
+
+    if buf.pop() != Some(0) {
+        return Err(Error::Io {
+            source: std::io::ErrorKind::UnexpectedEof.into(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+// numbers are stored little endian, prefixed with their length
+fn read_number<I: Read>(input: &mut I) -> Result<BigUint, Error> {
+    let length = input.read_u32::<LittleEndian>().context(IoSnafu)?;
+    let mut buf = Vec::new();
+    input
+        .take(u64::from(length))
+        .read_to_end(&mut buf)
+        .context(IoSnafu)?;
+
+    if buf.len() != length as usize {
+        return Err(Error::Io {
+            source: std::io::ErrorKind::UnexpectedEof.into(),
+        });
+    }
+
+    Ok(BigUint::from_bytes_le(&buf))
+}
+
+impl PublicKey {
+    fn read<I: Read>(input: &mut I) -> Result<Self, Error> {
+        // length of everything below, which is already implied by the bit length
+        input.read_u32::<LittleEndian>().context(IoSnafu)?;
+
+        let mut header = [0; 12];
+        input.read_exact(&mut header).context(IoSnafu)?;
+        ensure!(
+            header[..8] == PUBLIC_KEY_BLOB && &header[8..] == b"RSA1",
+            InvalidKeySnafu
+        );
+
+        let bits = input.read_u32::<LittleEndian>().context(IoSnafu)?;
+        let exponent = input.read_u32::<LittleEndian>().context(IoSnafu)?;
+        ensure!(bits > 0 && bits % 8 == 0 && bits <= 16384, InvalidKeySnafu);
+
+        let length = bits as usize / 8;
+        let mut modulus = vec![0; length];
+        input.read_exact(&mut modulus).context(IoSnafu)?;
+
+        Ok(Self {
+            exponent,
+            modulus: BigUint::from_bytes_le(&modulus),
+            length,
+        })
+    }
+
+    // PKCS #1 v1.5 padding of a SHA-1 digest
+    fn pad(&self, hash: &[u8]) -> BigUint {
+        let mut padded = vec![0x00, 0x01];
+        padded.resize(self.length.saturating_sub(36), 0xFF);
+        padded.extend([
+            0x00, 0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2B, 0x0E, 0x03, 0x02, 0x1A, 0x05, 0x00,
+            0x04, 0x14,
+        ]);
+        padded.extend(hash);
+
+        BigUint::from_bytes_be(&padded)
+    }
+
+    fn verifies(&self, signature: &BigUint, hash: &[u8]) -> bool {
+        signature.modpow(&BigUint::from(self.exponent), &self.modulus) == self.pad(hash)
+    }
+}
+
+impl BiKey {
+    pub fn read<I: BufRead>(mut input: I) -> Result<Self, Error> {
+        let authority = read_string(&mut input)?;
+        let key = PublicKey::read(&mut input)?;
+
+        Ok(Self { authority, key })
+    }
+}
+
+impl BiSign {
+    pub fn read<I: BufRead>(mut input: I) -> Result<Self, Error> {
+        let authority = read_string(&mut input)?;
+        let key = PublicKey::read(&mut input)?;
+
+        let first = read_number(&mut input)?;
+        let version = match input.read_u32::<LittleEndian>().context(IoSnafu)? {
+            2 => Version::V2,
+            3 => Version::V3,
+            version => return UnknownVersionSnafu { version }.fail(),
+        };
+        let second = read_number(&mut input)?;
+        let third = read_number(&mut input)?;
+
+        Ok(Self {
+            authority,
+            key,
+            version,
+            signatures: [first, second, third],
+        })
+    }
+
+    // checks the signature against a key, which has to be the one that made it
+    pub fn verify<I: BufRead + Seek>(&self, key: &BiKey, pbo: &mut Pbo<I>) -> Result<(), Error> {
+        ensure!(
+            self.authority == key.authority,
+            AuthorityMismatchSnafu {
+                signed_by: &self.authority,
+                key: &key.authority
+            }
+        );
+        ensure!(
+            self.key == key.key,
+            KeyMismatchSnafu {
+                key: &key.authority
+            }
+        );
+
+        let hashes = hashes(pbo, self.version)?;
+
+        for (index, (signature, hash)) in self.signatures.iter().zip(&hashes).enumerate() {
+            ensure!(
+                key.key.verifies(signature, hash),
+                InvalidSignatureSnafu { index: index + 1 }
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn extension(name: &str) -> String {
+    let file_name = name.rsplit(['\\', '/']).next().unwrap_or(name);
+
+    file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default()
+}
+
+// the three hashes a bisign signs: the whole pbo, its file names and the contents of its scripts
+pub fn hashes<I: BufRead + Seek>(
+    pbo: &mut Pbo<I>,
+    version: Version,
+) -> Result<[Vec<u8>; 3], Error> {
+    let pbo_hash = pbo.hash().context(PboReadSnafu)?;
+
+    // the header entry has no name, so it's skipped along with everything else that's empty
+    let mut files: Vec<_> = (0..pbo.entries.len())
+        .filter(|index| !pbo.entries[*index].filename.is_empty())
+        .map(|index| (pbo.entries[index].filename.to_lowercase(), index))
+        .collect();
+    files.sort();
+
+    let mut name_hasher = Sha1::new();
+    let mut file_hasher = Sha1::new();
+    let mut nothing = true;
+
+    for (name, index) in files {
+        if pbo.entries[index].data_size > 0 {
+            name_hasher.update(name.as_bytes());
+        }
+
+        let extension = extension(&name);
+        let included = match version {
+            Version::V2 => !V2_EXCLUDED.contains(&extension.as_str()),
+            Version::V3 => V3_INCLUDED.contains(&extension.as_str()),
+        };
+
+        if included {
+            file_hasher.update(pbo.read_stored_at(index).context(PboReadSnafu)?);
+            nothing = false;
+        }
+    }
+
+    if nothing {
+        file_hasher.update(match version {
+            Version::V2 => b"nothing",
+            Version::V3 => b"gnihton",
+        });
+    }
+
+    let name_hash = name_hasher.finalize();
+    let file_hash = file_hasher.finalize();
+
+    let mut prefix = pbo.prefix().unwrap_or_default().to_string();
+    if !prefix.is_empty() && !prefix.ends_with('\\') {
+        prefix.push('\\');
+    }
+
+    let mut second = Sha1::new();
+    second.update(pbo_hash);
+    second.update(name_hash);
+    second.update(prefix.as_bytes());
+
+    let mut third = Sha1::new();
+    third.update(file_hash);
+    third.update(name_hash);
+    third.update(prefix.as_bytes());
+
+    Ok([
+        pbo_hash.to_vec(),
+        second.finalize().to_vec(),
+        third.finalize().to_vec(),
+    ])
+}
+
+// keys and signatures made on the fly, for tests that need many variations of a signed mod
+#[cfg(test)]
+pub mod test_support {
+    use super::*;
+    use crate::pbo::PboWriter;
+    use std::io::Cursor;
+
+    // a 1024 bit key generated for these tests
+    const MODULUS: &str = "9637E7614BD7CE3373C906C156F1AE9B402B16610B8A1E6521CDEDD9421A96A086AF3C4F6C74EDEAA0677E6B1DB0C521DBF45C3EFFE2D0CCD45081915001B89D16A594E9B739FDCF8A28A80E0298A3EBA3CA37756E93E8EE7AE2F9F0DC45698FD03D9D055773B83CD3829FC89F08F0A6D96373EF3BE1AC61CF3D534E84FC833F";
+    const PRIVATE_EXPONENT: &str = "5D6596B3B4BF119285FBC7FA6F7F468B7A630FBFF8B7C05C2A9F69B179DB2429DC739970DC42FB25B4E384033F6B87B59C971ABF84C35604F440B5BE4E5B33976C01AD317F583E491C5EE9E272CDB32808F272365C45206BE9C77673A14175F275706EFA20B8030C355E3EAB8732206EB53825A692AA13C46A302D50522B9B79";
+
+    fn number(hex: &str) -> BigUint {
+        BigUint::parse_bytes(hex.as_bytes(), 16).unwrap()
+    }
+
+    pub fn bikey(authority: &str) -> Vec<u8> {
+        let mut bytes = authority.as_bytes().to_vec();
+        bytes.push(0);
+        bytes.extend(148u32.to_le_bytes());
+        bytes.extend(PUBLIC_KEY_BLOB);
+        bytes.extend(b"RSA1");
+        bytes.extend(1024u32.to_le_bytes());
+        bytes.extend(65537u32.to_le_bytes());
+        bytes.extend(number(MODULUS).to_bytes_le());
+
+        bytes
+    }
+
+    // signs the pbo the way the official tools do
+    pub fn bisign(authority: &str, pbo: &[u8]) -> Vec<u8> {
+        let key = BiKey::read(bikey(authority).as_slice()).unwrap();
+        let mut pbo = Pbo::read(Cursor::new(pbo)).unwrap();
+        let hashes = hashes(&mut pbo, Version::V3).unwrap();
+
+        let sign = |hash: &[u8], bytes: &mut Vec<u8>| {
+            let mut signature = key
+                .key
+                .pad(hash)
+                .modpow(&number(PRIVATE_EXPONENT), &key.key.modulus)
+                .to_bytes_le();
+            signature.resize(128, 0);
+
+            bytes.extend(128u32.to_le_bytes());
+            bytes.extend(signature);
+        };
+
+        let mut bytes = bikey(authority);
+        sign(&hashes[0], &mut bytes);
+        bytes.extend(3u32.to_le_bytes());
+        sign(&hashes[1], &mut bytes);
+        sign(&hashes[2], &mut bytes);
+
+        bytes
+    }
+
+    pub fn signed_pbo() -> Vec<u8> {
+        let mut writer = PboWriter::new();
+        writer.extension("prefix", "x\\test\\addons\\main");
+        writer
+            .add_data("script.sqf", b"hint \"hello\";".to_vec(), 0)
+            .unwrap();
+        writer
+            .add_data("texture.paa", b"not really a texture".to_vec(), 0)
+            .unwrap();
+
+        let mut pbo = Vec::new();
+        writer.write(&mut pbo).unwrap();
+
+        pbo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{bikey, bisign, signed_pbo};
+    use super::*;
+    use std::io::Cursor;
+
+    // signed by test_files/signatures/sign.py, which implements the scheme separately from this
+    // module, with a key only it used
+    #[test]
+    fn fixture_test() {
+        let dir: std::path::PathBuf = [env!("CARGO_MANIFEST_DIR"), "test_files", "signatures"]
+            .iter()
+            .collect();
+        let key = BiKey::read(
+            std::fs::read(dir.join("nimble_test.bikey"))
+                .unwrap()
+                .as_slice(),
+        )
+        .unwrap();
+        let sign = BiSign::read(
+            std::fs::read(dir.join("ace_advanced_ballistics.pbo.nimble_test.bisign"))
+                .unwrap()
+                .as_slice(),
+        )
+        .unwrap();
+        let pbo = include_bytes!("../test_files/@ace/addons/ace_advanced_ballistics.pbo").to_vec();
+
+        assert_eq!(key.authority, "nimble_test");
+        assert_eq!(sign.version, Version::V3);
+        sign.verify(&key, &mut Pbo::read(Cursor::new(&pbo)).unwrap())
+            .unwrap();
+
+        // the signed pbo with another file's contents changed, and its checksum fixed up
+        let mut tampered = pbo.clone();
+        let position = tampered
+            .windows(8)
+            .position(|window| window == b"#include")
+            .unwrap();
+        tampered[position + 1] = b'I';
+        let len = tampered.len();
+        let checksum = Sha1::digest(&tampered[..len - 21]);
+        tampered[len - 20..].copy_from_slice(&checksum);
+
+        assert!(matches!(
+            sign.verify(&key, &mut Pbo::read(Cursor::new(&tampered)).unwrap()),
+            Err(Error::InvalidSignature { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn verify_test() {
+        let pbo = signed_pbo();
+        let key = BiKey::read(bikey("test").as_slice()).unwrap();
+        let sign = BiSign::read(bisign("test", &pbo).as_slice()).unwrap();
+
+        assert_eq!(sign.version, Version::V3);
+        sign.verify(&key, &mut Pbo::read(Cursor::new(&pbo)).unwrap())
+            .unwrap();
+
+        let mut tampered = pbo.clone();
+        let len = tampered.len();
+        tampered[len - 30] ^= 0xFF;
+        let result = sign.verify(&key, &mut Pbo::read(Cursor::new(&tampered)).unwrap());
+        assert!(matches!(result, Err(Error::InvalidSignature { index: 1 })));
+
+        let other = BiKey::read(bikey("other").as_slice()).unwrap();
+        let result = sign.verify(&other, &mut Pbo::read(Cursor::new(&pbo)).unwrap());
+        assert!(matches!(result, Err(Error::AuthorityMismatch { .. })));
+    }
+}
@@ -0,0 +1,231 @@
+use percent_encoding::percent_decode_str;
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Error while requesting {}: {}", url, source))]
+    Http {
+        url: String,
+
+        #[snafu(source(from(ureq::Error, Box::new)))]
+        source: Box<ureq::Error>,
+    },
+    #[snafu(display("Error while reading {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("invalid file url: {}", url))]
+    InvalidFileUrl { url: String },
+    #[snafu(display("{} not found in bundle {}", file, path.display()))]
+    NotInBundle { path: PathBuf, file: String },
+}
+
+#[derive(Debug)]
+pub struct BundleEntry {
+    offset: u64,
+    size: u64,
+}
+
+// where repository data comes from: a swifty http server, a directory with the same layout or
+// a tar bundle created by nimble export
+pub enum Source {
+    Http {
+        agent: ureq::Agent,
+        base_url: String,
+    },
+    Local {
+        base_path: PathBuf,
+    },
+    Bundle {
+        path: PathBuf,
+        entries: HashMap<String, BundleEntry>,
+    },
+}
+
+pub struct Download {
+    pub reader: Box<dyn Read + Send>,
+    pub length: Option<u64>,
+}
+
+fn file_url_to_path(url: &str) -> Result<PathBuf, Error> {
+    let path = url
+        .strip_prefix("file://")
+        .and_then(|path| percent_decode_str(path).decode_utf8().ok())
+        .ok_or_else(|| Error::InvalidFileUrl {
+            url: url.to_string(),
+        })?;
+
+    // file:///C:/foo has its path as /C:/foo, which windows doesn't like
+    let bytes = path.as_bytes();
+    if cfg!(windows) && bytes.len() >= 3 && bytes[0] == b'/' && bytes[2] == b':' {
+        return Ok(PathBuf::from(&path[1..]));
+    }
+
+    Ok(PathBuf::from(path.as_ref()))
+}
+
+impl Source {
+    pub fn new(agent: ureq::Agent, repo_url: &str) -> Result<Self, Error> {
+        if repo_url.starts_with("http://") || repo_url.starts_with("https://") {
+            Ok(Self::Http {
+                agent,
+                base_url: repo_url.trim_end_matches('/').to_string(),
+            })
+        } else if repo_url.starts_with("file://") {
+            Ok(Self::Local {
+                base_path: file_url_to_path(repo_url)?,
+            })
+        } else {
+            Ok(Self::Local {
+                base_path: PathBuf::from(repo_url),
+            })
+        }
+    }
+
+    // tar entries can't be opened individually, so remember where each one's data starts
+    pub fn from_bundle(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).context(IoSnafu { path })?;
+        let mut archive = tar::Archive::new(BufReader::new(file));
+        let mut entries = HashMap::new();
+
+        for entry in archive.entries().context(IoSnafu { path })? {
+            let entry = entry.context(IoSnafu { path })?;
+
+            if entry.header().entry_type() != tar::EntryType::Regular {
+                continue;
+            }
+
+            let name = entry
+                .path()
+                .context(IoSnafu { path })?
+                .to_string_lossy()
+                .replace('\\', "/")
+                .to_lowercase();
+
+            entries.insert(
+                name,
+                BundleEntry {
+                    offset: entry.raw_file_position(),
+                    size: entry.size(),
+                },
+            );
+        }
+
+        Ok(Self::Bundle {
+            path: path.to_owned(),
+            entries,
+        })
+    }
+
+    // relative is a '/' separated path from the repository root, e.g. "@ace/mod.srf"
+    pub fn open(&self, relative: &str) -> Result<Download, Error> {
+        match self {
+            Self::Http { agent, base_url } => {
+                let url = format!("{base_url}/{relative}");
+                let response = agent.get(&url).call().context(HttpSnafu { url })?;
+
+                let length = response
+                    .header("Content-Length")
+                    .and_then(|len| len.parse().ok());
+
+                Ok(Download {
+                    reader: response.into_reader(),
+                    length,
+                })
+            }
+            Self::Local { base_path } => {
+                let path = base_path.join(relative);
+                let file = File::open(&path).context(IoSnafu { path: &path })?;
+                let length = file.metadata().context(IoSnafu { path: &path })?.len();
+
+                Ok(Download {
+                    reader: Box::new(BufReader::new(file)),
+                    length: Some(length),
+                })
+            }
+            Self::Bundle { path, entries } => {
+                // swifty mod names are case insensitive, and srf names are always lowercase
+                let entry = entries
+                    .get(&relative.to_lowercase())
+                    .context(NotInBundleSnafu {
+                        path,
+                        file: relative,
+                    })?;
+
+                let mut file = File::open(path).context(IoSnafu { path })?;
+                file.seek(SeekFrom::Start(entry.offset))
+                    .context(IoSnafu { path })?;
+
+                Ok(Download {
+                    reader: Box::new(BufReader::new(file).take(entry.size)),
+                    length: Some(entry.size),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent() -> ureq::Agent {
+        ureq::AgentBuilder::new().build()
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn file_url_is_a_local_source() {
+        let source = Source::new(agent(), "file:///mnt/nas/arma%20repo/").unwrap();
+
+        match source {
+            Source::Local { base_path } => {
+                assert_eq!(base_path, PathBuf::from("/mnt/nas/arma repo/"))
+            }
+            _ => panic!("file url was not treated as a local path"),
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn file_url_is_a_local_source() {
+        let source = Source::new(agent(), "file:///D:/arma%20repo/").unwrap();
+
+        match source {
+            Source::Local { base_path } => assert_eq!(base_path, PathBuf::from("D:/arma repo/")),
+            _ => panic!("file url was not treated as a local path"),
+        }
+    }
+
+    #[test]
+    fn reads_files_from_local_path() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let repo_path: PathBuf = [project_root, "test_files"].iter().collect();
+        let source = Source::new(agent(), &repo_path.to_string_lossy()).unwrap();
+
+        let mut download = source
+            .open("@ace/addons/ace_advanced_ballistics.pbo")
+            .unwrap();
+
+        let mut buf = Vec::new();
+        download.reader.read_to_end(&mut buf).unwrap();
+
+        assert_eq!(download.length, Some(buf.len() as u64));
+        assert_eq!(buf.len(), 200888);
+    }
+
+    #[test]
+    fn http_base_url_has_no_trailing_slash() {
+        match Source::new(agent(), "https://example.com/swifty/").unwrap() {
+            Source::Http { base_url, .. } => {
+                assert_eq!(base_url, "https://example.com/swifty")
+            }
+            _ => panic!("http url was not treated as http"),
+        }
+    }
+}
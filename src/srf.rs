@@ -16,10 +16,10 @@ use walkdir::WalkDir;
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct Part {
-    path: String,
-    length: u64,
-    start: u64,
-    checksum: String,
+    pub path: String,
+    pub length: u64,
+    pub start: u64,
+    pub checksum: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -142,8 +142,14 @@ pub fn scan_pbo(path: &Path, base_path: &Path) -> Result<File, Error> {
     }
 
     {
-        // TODO: this once panicked due to underflow.
-        let remaining_len = length - offset;
+        // Pbo::read already checks this, but a file growing underneath us shouldn't panic
+        let remaining_len = length
+            .checked_sub(offset)
+            .ok_or(crate::pbo::Error::Truncated {
+                expected: offset,
+                length,
+            })
+            .context(PboSnafu)?;
 
         let end_hash = generate_hash(pbo.input, remaining_len)?;
         parts.push(Part {
@@ -175,21 +181,20 @@ pub fn scan_pbo(path: &Path, base_path: &Path) -> Result<File, Error> {
     })
 }
 
-pub fn scan_file(path: &Path, base_path: &Path) -> Result<File, Error> {
-    let file = std::fs::File::open(path).context(IoSnafu)?;
+// swifty hashes files in 5 MB parts
+fn hash_parts<R: Read>(reader: &mut R, file_name: &str) -> Result<Vec<Part>, io::Error> {
     let mut parts = Vec::new();
-
-    let file_len = file.metadata().context(IoSnafu)?.len();
-
-    let mut reader = BufReader::new(file);
     let mut pos = 0;
 
-    while pos < file_len {
+    loop {
         let mut hasher = Md5::new();
         let mut stream = reader.by_ref().take(5000000);
 
         let pre_copy_pos = pos;
-        let copied = std::io::copy(&mut stream, &mut hasher).context(IoSnafu {})?;
+        let copied = std::io::copy(&mut stream, &mut hasher)?;
+        if copied == 0 {
+            break;
+        }
         pos += copied;
 
         let hash = hasher.finalize();
@@ -197,38 +202,65 @@ pub fn scan_file(path: &Path, base_path: &Path) -> Result<File, Error> {
         parts.push(Part {
             checksum: format!("{hash:X}"),
             length: copied,
-            path: format!(
-                "{}_{}",
-                path.components()
-                    .last()
-                    .unwrap()
-                    .as_os_str()
-                    .to_string_lossy(),
-                pos
-            ),
+            path: format!("{file_name}_{pos}"),
             start: pre_copy_pos,
         });
     }
 
-    // final checksum generation
-    // swifty hashes the checksum strings
+    Ok(parts)
+}
+
+// swifty hashes the checksum strings
+fn parts_checksum(parts: &[Part]) -> String {
     let mut hasher = Md5::new();
 
-    for part in &parts {
+    for part in parts {
         hasher.update(&part.checksum);
     }
 
+    format!("{:X}", hasher.finalize())
+}
+
+// the checksum scan_file would give a regular file with these contents
+pub fn file_checksum<R: Read>(reader: &mut R) -> Result<String, io::Error> {
+    Ok(parts_checksum(&hash_parts(reader, "")?))
+}
+
+pub fn scan_file(path: &Path, base_path: &Path) -> Result<File, Error> {
+    let file = std::fs::File::open(path).context(IoSnafu)?;
+    let file_name = path
+        .components()
+        .next_back()
+        .unwrap()
+        .as_os_str()
+        .to_string_lossy();
+
+    let parts = hash_parts(&mut BufReader::new(file), &file_name).context(IoSnafu)?;
+    let length = parts.iter().map(|part| part.length).sum();
+
     let path = RelativePathBuf::from_path(path.strip_prefix(base_path).unwrap()).unwrap();
 
     Ok(File {
-        checksum: format!("{:X}", hasher.finalize()),
-        length: pos,
+        checksum: parts_checksum(&parts),
+        length,
         parts,
         path,
         r#type: FileType::File,
     })
 }
 
+// hashes a file the way it was scanned before, to check that its contents didn't change since
+pub fn rescan_checksum(path: &Path, r#type: &FileType) -> Result<String, Error> {
+    let base_path = path.parent().unwrap_or(path);
+
+    let file = match r#type {
+        FileType::Pbo => scan_pbo(path, base_path)?,
+        FileType::File => scan_file(path, base_path)?,
+    };
+
+    Ok(file.checksum)
+}
+
 fn recurse(path: &Path, base_path: &Path) -> Result<Vec<File>, Error> {
     println!("recursing into {:#?}", &path);
 
@@ -253,7 +285,17 @@ fn recurse(path: &Path, base_path: &Path) -> Result<Vec<File>, Error> {
             let extension = path.extension();
 
             match extension {
-                Some(extension) if extension == "pbo" => scan_pbo(path, base_path),
+                Some(extension) if extension == "pbo" => match scan_pbo(path, base_path) {
+                    // the game won't load it either way, but it still has to be synced byte for byte
+                    Err(Error::Pbo { source }) => {
+                        println!(
+                            "{} is not a valid pbo ({source}), hashing it as a regular file",
+                            path.display()
+                        );
+                        scan_file(path, base_path)
+                    }
+                    result => result,
+                },
                 _ => scan_file(path, base_path),
             }
         })
@@ -288,7 +330,7 @@ pub fn scan_mod(path: &Path) -> Result<Mod, Error> {
     Ok(Mod {
         name: path
             .components()
-            .last()
+            .next_back()
             .unwrap()
             .as_os_str()
             .to_string_lossy()
@@ -479,7 +521,7 @@ pub fn deserialize_legacy_srf<I: BufRead + Seek>(input: &mut I) -> Result<Mod, E
         files.push(file);
     }
 
-    Ok(addon)
+    Ok(Mod { files, ..addon })
 }
 
 #[cfg(test)]
@@ -499,6 +541,7 @@ mod tests {
             deserialized.checksum,
             Md5Digest::new("44C1B8021822F80E1E560689D2AAB0BF").unwrap()
         );
+        assert_eq!(deserialized.files.len(), 19);
     }
 
     #[test]
@@ -516,4 +559,20 @@ mod tests {
             Md5Digest::new("787662722D70C36DF28CD1D5EE8D8E86").unwrap()
         );
     }
+
+    #[test]
+    fn corrupt_pbos_are_hashed_as_files() {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+        let r#mod = scan_mod(
+            &[project_root, "test_files", "@corrupt"]
+                .iter()
+                .collect::<PathBuf>(),
+        )
+        .unwrap();
+
+        assert_eq!(r#mod.files.len(), 4);
+        for file in &r#mod.files {
+            assert!(matches!(file.r#type, FileType::File), "{}", file.path);
+        }
+    }
 }
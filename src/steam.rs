@@ -0,0 +1,411 @@
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+pub const ARMA_APP_ID: &str = "107410";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("failed to read {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("syntax error in {} on line {}: {}", path.display(), line, message))]
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        message: &'static str,
+    },
+    #[snafu(display("could not find a Steam installation, pass --steam-root"))]
+    NoSteamRoot,
+    #[snafu(display("Arma 3 is not installed in any Steam library of {}", steam_root.display()))]
+    NotInstalled { steam_root: PathBuf },
+    #[snafu(display("{} has no installdir", path.display()))]
+    MissingInstallDir { path: PathBuf },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Object(Object),
+}
+
+// keys can repeat and their case is not reliable, so entries are kept as they appear
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Object(pub Vec<(String, Value)>);
+
+impl Object {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0
+            .iter()
+            .find(|(entry_key, _)| entry_key.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn object(&self, key: &str) -> Option<&Object> {
+        match self.get(key) {
+            Some(Value::Object(object)) => Some(object),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(Value::String(string)) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = (&str, &Object)> {
+        self.0.iter().filter_map(|(key, value)| match value {
+            Value::Object(object) => Some((key.as_str(), object)),
+            Value::String(_) => None,
+        })
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+// syntax errors are reported without a path, read() adds it
+type ParseError = (usize, &'static str);
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+
+        c
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.next();
+            } else if c == '/' {
+                // comments are the only thing starting with a slash
+                while let Some(c) = self.next() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<String, ParseError> {
+        let mut token = String::new();
+
+        if self.chars.peek() == Some(&'"') {
+            self.next();
+
+            loop {
+                match self.next() {
+                    Some('"') => return Ok(token),
+                    Some('\\') => match self.next() {
+                        Some('n') => token.push('\n'),
+                        Some('t') => token.push('\t'),
+                        Some(c) => token.push(c),
+                        None => return Err((self.line, "unterminated string")),
+                    },
+                    Some(c) => token.push(c),
+                    None => return Err((self.line, "unterminated string")),
+                }
+            }
+        }
+
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+                break;
+            }
+
+            token.push(c);
+            self.next();
+        }
+
+        Ok(token)
+    }
+
+    // platform conditionals like [$WIN32] are skipped, whatever they say
+    fn skip_conditional(&mut self) {
+        self.skip_trivia();
+
+        if self.chars.peek() == Some(&'[') {
+            while let Some(c) = self.next() {
+                if c == ']' {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn object(&mut self, nested: bool) -> Result<Object, ParseError> {
+        let mut entries = Vec::new();
+
+        loop {
+            self.skip_trivia();
+
+            match self.chars.peek() {
+                None if nested => return Err((self.line, "unterminated object")),
+                None => return Ok(Object(entries)),
+                Some('}') if nested => {
+                    self.next();
+                    return Ok(Object(entries));
+                }
+                Some('}') => return Err((self.line, "unexpected }")),
+                _ => {}
+            }
+
+            let key = self.token()?;
+            if key.is_empty() {
+                return Err((self.line, "expected a key"));
+            }
+
+            self.skip_trivia();
+            let value = if self.chars.peek() == Some(&'{') {
+                self.next();
+                Value::Object(self.object(true)?)
+            } else {
+                Value::String(self.token()?)
+            };
+
+            self.skip_conditional();
+            entries.push((key, value));
+        }
+    }
+}
+
+// parses Valve's KeyValues text format, used by libraryfolders.vdf and appmanifest files
+pub fn parse(input: &str) -> Result<Object, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+        line: 1,
+    };
+
+    parser.object(false)
+}
+
+pub fn read(path: &Path) -> Result<Object, Error> {
+    let input = std::fs::read(path).context(IoSnafu { path })?;
+
+    parse(&String::from_utf8_lossy(&input)).map_err(|(line, message)| Error::Syntax {
+        path: path.to_owned(),
+        line,
+        message,
+    })
+}
+
+// the usual places Steam installs itself to
+pub fn default_root() -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = if cfg!(windows) {
+        vec![PathBuf::from("C:\\Program Files (x86)\\Steam")]
+    } else {
+        let home = PathBuf::from(std::env::var_os("HOME")?);
+
+        vec![
+            home.join(".steam").join("steam"),
+            home.join(".local").join("share").join("Steam"),
+            home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+            home.join("Library/Application Support/Steam"),
+        ]
+    };
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.join("steamapps").is_dir())
+}
+
+// the library an app is installed in, according to libraryfolders.vdf
+pub fn library_for_app(library_folders: &Object, app_id: &str) -> Option<PathBuf> {
+    library_folders
+        .object("libraryfolders")?
+        .objects()
+        .find(|(_, library)| {
+            library
+                .object("apps")
+                .is_some_and(|apps| apps.get(app_id).is_some())
+        })
+        .and_then(|(_, library)| library.string("path"))
+        .map(PathBuf::from)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arma {
+    pub steam_root: PathBuf,
+    pub install_dir: PathBuf,
+    // only exists when the game is run through Proton
+    pub prefix: Option<PathBuf>,
+}
+
+impl Arma {
+    pub fn executable(&self) -> PathBuf {
+        self.install_dir.join("arma3_x64.exe")
+    }
+
+    // the linux port, which steam installs instead of the windows build when proton is off
+    pub fn native_executable(&self) -> PathBuf {
+        self.install_dir.join("arma3.x86_64")
+    }
+
+    // the directory proton keeps the prefix in, which is what it expects in STEAM_COMPAT_DATA_PATH
+    pub fn compat_data(&self) -> Option<&Path> {
+        self.prefix.as_deref().and_then(Path::parent)
+    }
+
+    pub fn drive_c(&self) -> Option<PathBuf> {
+        self.prefix.as_ref().map(|prefix| prefix.join("drive_c"))
+    }
+
+    // workshop items are downloaded to the library the game is installed in
+    pub fn workshop_item(&self, published_id: u64) -> PathBuf {
+        let steamapps = self
+            .install_dir
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(&self.install_dir);
+
+        steamapps
+            .join("workshop")
+            .join("content")
+            .join(ARMA_APP_ID)
+            .join(published_id.to_string())
+    }
+}
+
+pub fn find_arma(steam_root: &Path) -> Result<Arma, Error> {
+    let library_folders_path = steam_root.join("steamapps").join("libraryfolders.vdf");
+    let library_folders = read(&library_folders_path)?;
+
+    // the root library is not always listed, but is always there
+    let library = library_for_app(&library_folders, ARMA_APP_ID)
+        .into_iter()
+        .chain(std::iter::once(steam_root.to_owned()))
+        .find(|library| {
+            library
+                .join("steamapps")
+                .join(format!("appmanifest_{ARMA_APP_ID}.acf"))
+                .is_file()
+        })
+        .context(NotInstalledSnafu { steam_root })?;
+
+    let steamapps = library.join("steamapps");
+    let manifest_path = steamapps.join(format!("appmanifest_{ARMA_APP_ID}.acf"));
+    let manifest = read(&manifest_path)?;
+
+    let install_dir = manifest
+        .object("AppState")
+        .and_then(|app_state| app_state.string("installdir"))
+        .context(MissingInstallDirSnafu {
+            path: &manifest_path,
+        })?;
+
+    let prefix = steamapps.join("compatdata").join(ARMA_APP_ID).join("pfx");
+
+    Ok(Arma {
+        steam_root: steam_root.to_owned(),
+        install_dir: steamapps.join("common").join(install_dir),
+        prefix: prefix.is_dir().then_some(prefix),
+    })
+}
+
+// finds Arma 3 in the given Steam root, or wherever Steam usually is
+pub fn find_arma_in(steam_root: Option<&Path>) -> Result<Arma, Error> {
+    match steam_root {
+        Some(steam_root) => find_arma(steam_root),
+        None => find_arma(&default_root().context(NoSteamRootSnafu)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        let project_root = env!("CARGO_MANIFEST_DIR");
+
+        [project_root, "test_files", "steam", name].iter().collect()
+    }
+
+    #[test]
+    fn library_folders_test() {
+        let library_folders = read(&fixture("libraryfolders.vdf")).unwrap();
+
+        assert_eq!(
+            library_for_app(&library_folders, ARMA_APP_ID),
+            Some(PathBuf::from("/mnt/games/SteamLibrary"))
+        );
+        assert_eq!(
+            library_for_app(&library_folders, "228980"),
+            Some(PathBuf::from("/home/user/.local/share/Steam"))
+        );
+        assert_eq!(library_for_app(&library_folders, "1"), None);
+    }
+
+    #[test]
+    fn app_manifest_test() {
+        let manifest = read(&fixture("appmanifest_107410.acf")).unwrap();
+        let app_state = manifest.object("AppState").unwrap();
+
+        assert_eq!(app_state.string("installdir"), Some("Arma 3"));
+        assert_eq!(
+            app_state
+                .object("UserConfig")
+                .and_then(|config| config.string("language")),
+            Some("english")
+        );
+    }
+
+    #[test]
+    fn find_arma_test() {
+        let steam_root = tempfile::tempdir().unwrap();
+        let library = tempfile::tempdir().unwrap();
+
+        std::fs::create_dir_all(steam_root.path().join("steamapps")).unwrap();
+        let library_folders = format!(
+            "\"libraryfolders\"\n{{\n\t\"0\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t\t\"apps\"\n\t\t{{\n\t\t\t\"107410\"\t\t\"1\"\n\t\t}}\n\t}}\n}}\n",
+            // steam escapes backslashes, like in windows paths
+            steam_root.path().display().to_string().replace('\\', "\\\\"),
+            library.path().display().to_string().replace('\\', "\\\\")
+        );
+        std::fs::write(
+            steam_root
+                .path()
+                .join("steamapps")
+                .join("libraryfolders.vdf"),
+            library_folders,
+        )
+        .unwrap();
+
+        let steamapps = library.path().join("steamapps");
+        let prefix = steamapps.join("compatdata").join(ARMA_APP_ID).join("pfx");
+        std::fs::create_dir_all(&prefix).unwrap();
+        std::fs::copy(
+            fixture("appmanifest_107410.acf"),
+            steamapps.join("appmanifest_107410.acf"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            find_arma(steam_root.path()).unwrap(),
+            Arma {
+                steam_root: steam_root.path().to_owned(),
+                install_dir: steamapps.join("common").join("Arma 3"),
+                prefix: Some(prefix),
+            }
+        );
+        assert_eq!(
+            find_arma(steam_root.path())
+                .unwrap()
+                .workshop_item(463939057),
+            steamapps.join("workshop/content/107410/463939057")
+        );
+    }
+}
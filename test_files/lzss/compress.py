@@ -0,0 +1,99 @@
+# packs a directory into a pbo with LZSS compressed (Cprs) entries, the way BI's tools store them.
+# written separately from src/lzss.rs so the fixture it produces doesn't share the code it tests.
+#
+#     python3 compress.py <directory> <prefix> <out.pbo>
+#
+# the encoder is greedy: at every position it takes the longest match within the last 4095 bytes,
+# where bytes before the start of the data count as spaces
+import hashlib
+import struct
+import sys
+from pathlib import Path
+
+CPRS = 0x43707273
+VERS = 0x56657273
+MAX_DISTANCE = 0xFFF
+MIN_LENGTH = 3
+MAX_LENGTH = 0xF + MIN_LENGTH
+
+
+def byte_at(data, position):
+    return data[position] if position >= 0 else ord(" ")
+
+
+def longest_match(data, position):
+    max_length = min(MAX_LENGTH, len(data) - position)
+    best_length, best_distance = 0, 0
+
+    # anything further back than the start plus a full match only sees spaces
+    for distance in range(1, min(MAX_DISTANCE, position + MAX_LENGTH) + 1):
+        length = 0
+        while (
+            length < max_length
+            and byte_at(data, position - distance + length) == data[position + length]
+        ):
+            length += 1
+
+        if length > best_length:
+            best_length, best_distance = length, distance
+            if length == max_length:
+                break
+
+    return best_length, best_distance
+
+
+def compress(data):
+    out = bytearray()
+    position = 0
+
+    while position < len(data):
+        flags = 0
+        items = bytearray()
+
+        for bit in range(8):
+            if position >= len(data):
+                break
+
+            length, distance = longest_match(data, position)
+            if length >= MIN_LENGTH:
+                items += bytes([distance & 0xFF, ((distance >> 4) & 0xF0) | (length - MIN_LENGTH)])
+                position += length
+            else:
+                flags |= 1 << bit
+                items.append(data[position])
+                position += 1
+
+        out.append(flags)
+        out += items
+
+    out += struct.pack("<I", sum(data) & 0xFFFFFFFF)
+    return bytes(out)
+
+
+def entry_header(name, mime, original_size, data_size):
+    return name.encode("latin-1") + b"\0" + struct.pack("<5I", mime, original_size, 0, 0, data_size)
+
+
+def main():
+    directory, prefix, out = Path(sys.argv[1]), sys.argv[2], Path(sys.argv[3])
+    files = sorted(path for path in directory.rglob("*") if path.is_file())
+
+    header = entry_header("", VERS, 0, 0) + b"prefix\0" + prefix.encode("latin-1") + b"\0\0"
+    data = b""
+
+    for path in files:
+        name = str(path.relative_to(directory)).replace("/", "\\")
+        contents = path.read_bytes()
+        compressed = compress(contents)
+
+        header += entry_header(name, CPRS, len(contents), len(compressed))
+        data += compressed
+
+    header += entry_header("", 0, 0, 0)
+    pbo = header + data
+
+    out.write_bytes(pbo + b"\0" + hashlib.sha1(pbo).digest())
+
+
+if __name__ == "__main__":
+    main()
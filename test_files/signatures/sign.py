@@ -0,0 +1,127 @@
+# signs a pbo the way DSSignFile does, written separately from src/signature.rs so the fixtures
+# it produces don't share the code they test.
+#
+#     openssl genrsa -out key.pem 1024
+#     python3 sign.py key.pem <authority> <pbo> <version>
+#
+# writes <authority>.bikey and <pbo>.<authority>.bisign into the current directory. the fixtures
+# here sign ../@ace/addons/ace_advanced_ballistics.pbo
+import hashlib
+import re
+import struct
+import subprocess
+import sys
+from pathlib import Path
+
+V2_EXCLUDED = {"paa", "jpg", "p3d", "tga", "rvmat", "lip", "ogg", "wss", "png", "rtm", "pac", "fxy", "wrp"}
+V3_INCLUDED = {"sqf", "inc", "bikb", "ext", "fsm", "sqm", "hpp", "cfg", "sqs", "h"}
+SHA1_DIGEST_INFO = bytes.fromhex("3021300906052b0e03021a05000414")
+
+
+def read_cstring(data, offset):
+    end = data.index(b"\0", offset)
+    return data[offset:end].decode("latin-1"), end + 1
+
+
+def read_pbo(data):
+    offset = 0
+    properties = {}
+    entries = []
+
+    while True:
+        name, offset = read_cstring(data, offset)
+        mime, _, _, _, size = struct.unpack_from("<5I", data, offset)
+        offset += 20
+
+        if name == "" and mime == 0x56657273:
+            while True:
+                key, offset = read_cstring(data, offset)
+                if key == "":
+                    break
+                value, offset = read_cstring(data, offset)
+                properties[key] = value
+        elif name == "":
+            break
+        else:
+            entries.append((name, size))
+
+    contents = []
+    for name, size in entries:
+        contents.append((name, data[offset:offset + size]))
+        offset += size
+
+    return properties, contents, data[offset + 1:offset + 21]
+
+
+def extension(name):
+    file_name = re.split(r"[\\/]", name)[-1]
+    return file_name.rsplit(".", 1)[1].lower() if "." in file_name else ""
+
+
+def hashes(data, version):
+    properties, contents, checksum = read_pbo(data)
+    assert checksum == hashlib.sha1(data[:len(data) - 21]).digest()
+
+    # BI hashes files in order of their lowercased names, not in header order
+    contents = sorted(((name.lower(), content) for name, content in contents), key=lambda c: c[0])
+
+    names = hashlib.sha1()
+    files = hashlib.sha1()
+    nothing = True
+    for name, content in contents:
+        if len(content) > 0:
+            names.update(name.encode("latin-1"))
+
+        ext = extension(name)
+        included = ext not in V2_EXCLUDED if version == 2 else ext in V3_INCLUDED
+        if included:
+            files.update(content)
+            nothing = False
+
+    if nothing:
+        files.update(b"nothing" if version == 2 else b"gnihton")
+
+    prefix = properties.get("prefix", "")
+    if prefix and not prefix.endswith("\\"):
+        prefix += "\\"
+    prefix = prefix.encode("latin-1")
+
+    second = hashlib.sha1(checksum + names.digest() + prefix).digest()
+    third = hashlib.sha1(files.digest() + names.digest() + prefix).digest()
+
+    return [checksum, second, third]
+
+
+def rsa_key(pem):
+    text = subprocess.run(["openssl", "rsa", "-in", pem, "-noout", "-text"], capture_output=True, text=True, check=True).stdout
+
+    def number(label):
+        block = re.search(label + r":\s*\n((?:\s+[0-9a-f:]+\n)+)", text).group(1)
+        return int(re.sub(r"[\s:]", "", block), 16)
+
+    return number("modulus"), number("privateExponent")
+
+
+def main():
+    pem, authority, pbo_path, version = sys.argv[1], sys.argv[2], Path(sys.argv[3]), int(sys.argv[4])
+    modulus, private_exponent = rsa_key(pem)
+    length = (modulus.bit_length() + 7) // 8
+
+    public_key = struct.pack("<8s4sII", bytes.fromhex("0602000000240000"), b"RSA1", length * 8, 65537)
+    public_key += modulus.to_bytes(length, "little")
+    bikey = authority.encode() + b"\0" + struct.pack("<I", len(public_key)) + public_key
+
+    def sign(digest):
+        padded = b"\x00\x01" + b"\xff" * (length - 3 - len(SHA1_DIGEST_INFO) - len(digest)) + b"\x00" + SHA1_DIGEST_INFO + digest
+        signature = pow(int.from_bytes(padded, "big"), private_exponent, modulus)
+        return struct.pack("<I", length) + signature.to_bytes(length, "little")
+
+    first, second, third = hashes(pbo_path.read_bytes(), version)
+    bisign = bikey + sign(first) + struct.pack("<I", version) + sign(second) + sign(third)
+
+    Path(f"{authority}.bikey").write_bytes(bikey)
+    Path(f"{pbo_path.name}.{authority}.bisign").write_bytes(bisign)
+
+
+if __name__ == "__main__":
+    main()